@@ -0,0 +1,268 @@
+//! Configurations shared by the serializer and the deserializer.
+//!
+//! The default configuration is what `rlp::to_bytes` and `rlp::from_bytes` use,
+//! which follows the yellow paper and keeps compatible with go-ethereum.
+//! A configuration can (de)serialize values by itself, so a profile can be 
+//! defined once and shared:
+//!
+//! ```rust
+//! # use serde::{Serialize, Deserialize};
+//! # use serlp::{Config, config::{EnumRepr, SignedRepr}};
+//! #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+//! enum Simple {
+//!     Empty,
+//!     Int(i32)
+//! }
+//!
+//! const PROFILE: Config = Config::new()
+//!     .with_enum_repr(EnumRepr::Indexed)
+//!     .with_signed_repr(SignedRepr::ZigZag)
+//!     .with_strict(true);
+//! let encoded = PROFILE.serialize(&Simple::Int(-3)).unwrap();
+//! assert_eq!(encoded, [0xc2, 0x01, 0x05]);
+//! assert_eq!(PROFILE.deserialize::<Simple>(&encoded).unwrap(), Simple::Int(-3));
+//! ```
+
+use serde::{Serialize, Deserialize};
+
+use crate::{error::Result, rlp};
+
+/// How enum variants are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumRepr {
+    /// The variant is dropped and only the payload is encoded,
+    /// unit variants are encoded as empty strings. Enums can not be
+    /// deserialized in this representation because the variant index
+    /// is lost. This is the default.
+    Transparent,
+    /// The variant is encoded as a list `[index, payload]`, where the payload
+    /// of tuple and struct variants is the list of their fields.
+    /// Unit variants are encoded as `[index]`.
+    Indexed,
+    /// The variant is encoded as a string `index || RLP(payload)`, i.e. a
+    /// single type byte followed by the payload, just like the typed transaction
+    /// envelope in EIP-2718. Unit variants are encoded as the type byte.
+    /// Only the first 256 variants can be encoded.
+    TypeByte
+}
+
+impl Default for EnumRepr {
+    fn default() -> Self {
+        EnumRepr::Transparent
+    }
+}
+
+/// How signed integers are encoded, the yellow paper only defines
+/// encoding of unsigned integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedRepr {
+    /// Signed integers are rejected with `Error::TypeNotSupported`.
+    /// This is the default.
+    Unsupported,
+    /// Map signed integers to unsigned ones with zigzag encoding
+    /// (0 => 0, -1 => 1, 1 => 2, -2 => 3, ...), then encode them
+    /// as unsigned integers.
+    ZigZag,
+    /// Encode signed integers as minimal big endian two's complement,
+    /// e.g. 0 => "", 127 => [0x7f], 128 => [0x00, 0x80], -1 => [0xff], 
+    /// -129 => [0xff, 0x7f].
+    TwosComplement
+}
+
+impl Default for SignedRepr {
+    fn default() -> Self {
+        SignedRepr::Unsupported
+    }
+}
+
+/// How a value without data is encoded, see `Config::with_unit_repr`, 
+/// `Config::with_unit_struct_repr` and `Config::with_unit_variant_repr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitRepr {
+    /// An empty string, i.e. 0x80.
+    EmptyString,
+    /// An empty list, i.e. 0xc0.
+    EmptyList
+}
+
+/// The default of `Config::with_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// The limit of `Config::with_max_input` used by `rlp::from_reader`, 
+/// which is the message size limit of devp2p (10 MiB).
+pub const DEFAULT_MAX_READ: usize = 10 << 20;
+
+/// The configuration of the (de)serializer, built with the `with_*` methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub(crate) strict: bool,
+    pub(crate) enum_repr: EnumRepr,
+    pub(crate) signed_repr: SignedRepr,
+    pub(crate) u8_seq_as_bytes: bool,
+    pub(crate) none_repr: UnitRepr,
+    pub(crate) unit_repr: UnitRepr,
+    pub(crate) unit_struct_repr: UnitRepr,
+    pub(crate) unit_variant_repr: UnitRepr,
+    pub(crate) max_depth: usize,
+    pub(crate) max_input: usize,
+    pub(crate) max_string_len: usize,
+    pub(crate) max_list_items: usize,
+    pub(crate) max_tree_nodes: usize
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    /// The default configuration, which is usable in constants.
+    pub const fn new() -> Self {
+        Self {
+            strict: false,
+            enum_repr: EnumRepr::Transparent,
+            signed_repr: SignedRepr::Unsupported,
+            u8_seq_as_bytes: false,
+            none_repr: UnitRepr::EmptyString,
+            unit_repr: UnitRepr::EmptyList,
+            unit_struct_repr: UnitRepr::EmptyString,
+            unit_variant_repr: UnitRepr::EmptyString,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_input: usize::MAX,
+            max_string_len: usize::MAX,
+            max_list_items: usize::MAX,
+            max_tree_nodes: usize::MAX
+        }
+    }
+
+    /// Only accept canonical encodings when deserializing,
+    /// see `de::Deserializer::new_strict`.
+    pub const fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set the representation of enums, see `EnumRepr`.
+    pub const fn with_enum_repr(mut self, enum_repr: EnumRepr) -> Self {
+        self.enum_repr = enum_repr;
+        self
+    }
+
+    /// Set the representation of signed integers, see `SignedRepr`.
+    pub const fn with_signed_repr(mut self, signed_repr: SignedRepr) -> Self {
+        self.signed_repr = signed_repr;
+        self
+    }
+
+    /// Encode sequences of `u8`, e.g. `Vec<u8>` and `[u8; N]`, as byte strings 
+    /// like every other RLP implementation does, instead of lists of single-byte 
+    /// integers. Serde does not tell fixed size arrays from tuples, so tuples of 
+    /// `u8` are byte strings too, while tuple structs are always lists. 
+    /// 
+    /// Without an element there is no way to tell the type of a sequence, so 
+    /// empty sequences are still empty lists (0xc0), use `serde_bytes` for byte 
+    /// strings which may be empty. When deserializing, byte strings are accepted 
+    /// for sequences and tuples of `u8` only, lists are still accepted as well.
+    pub const fn with_u8_seq_as_bytes(mut self, u8_seq_as_bytes: bool) -> Self {
+        self.u8_seq_as_bytes = u8_seq_as_bytes;
+        self
+    }
+
+    /// Set the representation of `None`, which is an empty string by default. 
+    /// Both representations are decoded as `None`, unless the field is tagged 
+    /// with `types::nil_string` or `types::nil_list` which override this setting.
+    pub const fn with_none_repr(mut self, none_repr: UnitRepr) -> Self {
+        self.none_repr = none_repr;
+        self
+    }
+
+    /// Set the representation of `()`, which is an empty list by default 
+    /// because it is an empty tuple.
+    /// 
+    /// Both representations are accepted when deserializing a unit or a unit 
+    /// struct, only the configured one is accepted in strict mode.
+    pub const fn with_unit_repr(mut self, unit_repr: UnitRepr) -> Self {
+        self.unit_repr = unit_repr;
+        self
+    }
+
+    /// Set the representation of unit structs, e.g. `struct Empty;` and 
+    /// `PhantomData<T>`, which is an empty string by default.
+    pub const fn with_unit_struct_repr(mut self, unit_struct_repr: UnitRepr) -> Self {
+        self.unit_struct_repr = unit_struct_repr;
+        self
+    }
+
+    /// Set the representation of unit variants in `EnumRepr::Transparent`, 
+    /// which is an empty string by default. The other enum representations 
+    /// always keep the variant index.
+    pub const fn with_unit_variant_repr(mut self, unit_variant_repr: UnitRepr) -> Self {
+        self.unit_variant_repr = unit_variant_repr;
+        self
+    }
+
+    /// Set the maximum nesting depth of lists (and `EnumRepr::TypeByte` payloads) 
+    /// when deserializing, deeper input is rejected with `Error::DepthLimitExceeded` 
+    /// instead of overflowing the stack. The default is `DEFAULT_MAX_DEPTH`.
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Reject inputs longer than `max_input` bytes with `Error::InputTooLarge`.
+    /// Unlimited by default, except for `rlp::from_reader` which uses `DEFAULT_MAX_READ`.
+    pub const fn with_max_input(mut self, max_input: usize) -> Self {
+        self.max_input = max_input;
+        self
+    }
+
+    /// Reject byte strings longer than `max_string_len` bytes with 
+    /// `Error::StringTooLong`. Unlimited by default.
+    pub const fn with_max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = max_string_len;
+        self
+    }
+
+    /// Reject lists with more than `max_list_items` items with 
+    /// `Error::TooManyItems`. Unlimited by default.
+    pub const fn with_max_list_items(mut self, max_list_items: usize) -> Self {
+        self.max_list_items = max_list_items;
+        self
+    }
+
+    /// Reject inputs with more than `max_tree_nodes` items (strings and lists) 
+    /// when building a `rlp::RlpTree`, with `Error::TooManyNodes`. 
+    /// Unlimited by default.
+    pub const fn with_max_tree_nodes(mut self, max_tree_nodes: usize) -> Self {
+        self.max_tree_nodes = max_tree_nodes;
+        self
+    }
+
+    /// Serialize `value` with this configuration, 
+    /// same as `rlp::to_bytes_with_config`.
+    pub fn serialize<T>(self, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        rlp::to_bytes_with_config(value, self)
+    }
+
+    /// The length of the encoding of `value` with this configuration, 
+    /// same as `rlp::encoded_len_with_config`.
+    pub fn serialized_size<T>(self, value: &T) -> Result<usize>
+    where
+        T: Serialize,
+    {
+        rlp::encoded_len_with_config(value, self)
+    }
+
+    /// Deserialize a value from `bytes` with this configuration, 
+    /// same as `rlp::from_bytes_with_config`.
+    pub fn deserialize<'a, T>(self, bytes: &'a [u8]) -> Result<T>
+    where
+        T: Deserialize<'a>,
+    {
+        rlp::from_bytes_with_config(bytes, self)
+    }
+}
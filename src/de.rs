@@ -3,55 +3,235 @@
 
 use std::mem;
 use serde::{de::{
-    self, DeserializeSeed, SeqAccess, Visitor,
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
 }, Deserialize};
 use byteorder::{BigEndian, ReadBytesExt};
 
-use crate::{error::{Error, Result}, rlp::RlpTree};
+use crate::{
+    config::{Config, EnumRepr, SignedRepr, UnitRepr},
+    error::{Error, Expected, Found, Result, Segment},
+    rlp::RlpTree,
+    types::Nil
+};
 use paste::paste;
 
 pub struct Deserializer<'de> {
-    input: &'de [u8]
+    input: &'de [u8],
+    /// the offset of `input` in the original input
+    offset: usize,
+    /// how many lists `input` is nested in
+    depth: usize,
+    config: Config,
+    /// the marker of `None` for the next option, set by `types::nil`, 
+    /// `types::nil_string` and `types::nil_list`
+    nil: Option<Nil>
+}
+
+/// The header of an RLP item.
+struct Header {
+    list: bool,
+    /// where the payload starts, relative to the header
+    start: usize,
+    /// the length of the payload
+    len: usize
 }
 
 impl<'de> Deserializer<'de> {
     /// Create a deserializer instance from a byte slice, this will covert 
     /// the slice into a tree and store it.
     pub fn new(input: &'de [u8]) -> Self {
-        Self { 
-            input
+        Self::with_config(input, Config::default())
+    }
+
+    /// Create a deserializer which only accepts canonical encodings, 
+    /// which is what go-ethereum does. The following forms are rejected 
+    /// with `Error::NonCanonical`:
+    /// 
+    /// - a single byte below 0x80 wrapped in a string header, e.g. `0x81 0x05`
+    /// - long form headers (0xb8..=0xbf, 0xf8..=0xff) for payloads shorter than 56 bytes
+    /// - length fields with leading zero bytes
+    pub fn new_strict(input: &'de [u8]) -> Self {
+        Self::with_config(input, Config::new().with_strict(true))
+    }
+
+    /// Create a deserializer with the given configuration.
+    pub fn with_config(input: &'de [u8], config: Config) -> Self {
+        Self {
+            input,
+            offset: 0,
+            depth: 0,
+            config,
+            nil: None
         }
     }
 
+    /// Create a deserializer for a sub slice of the input which starts at 
+    /// `offset` of the original input, all settings are inherited.
+    fn child(&self, input: &'de [u8], offset: usize) -> Self {
+        Self {
+            input,
+            offset,
+            depth: self.depth,
+            config: self.config,
+            nil: None
+        }
+    }
+
+    /// Create a deserializer for the payload of the next item, 
+    /// which is one level deeper.
+    fn nested(&self, input: &'de [u8], offset: usize) -> Result<Self> {
+        if self.depth >= self.config.max_depth {
+            return Err(Error::DepthLimitExceeded { offset: self.offset, limit: self.config.max_depth })
+        }
+        let mut nested = self.child(input, offset);
+        nested.depth += 1;
+        Ok(nested)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.input.is_empty()
     }
 
-    pub fn next_is_bytes(&self) -> bool {
-        self.input[0] < 192
+    /// The input which has not been consumed yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.input
     }
 
-    /// return value:
-    /// - RLP encoding of the byte slice,
-    /// - the byte slice,
-    /// - the Deserializer for remaining data
-    pub fn next_bytes(&self) -> Result<(&'de [u8], &'de [u8], Self)> {
+    /// The offset of the remaining input in the original input.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Check the whole input against `Config::with_max_input`.
+    pub(crate) fn check_input_len(&self) -> Result<()> {
+        let limit = self.config.max_input;
+        if self.input.len() > limit {
+            return Err(Error::InputTooLarge { len: self.input.len(), limit })
+        }
+        Ok(())
+    }
+
+    /// Check the number of items already read from the current list
+    /// against `Config::with_max_list_items` before reading the next one.
+    pub(crate) fn check_items(&self, count: usize) -> Result<()> {
+        let limit = self.config.max_list_items;
+        if count >= limit {
+            return Err(Error::TooManyItems { offset: self.offset, limit })
+        }
+        Ok(())
+    }
+
+    fn unexpected(&self, expected: Expected, found: Found) -> Error {
+        Error::MalformedData { offset: self.offset, expected, found }
+    }
+
+    /// Check if the next item is a byte array, 
+    /// returns `Error::MalformedData` if there is no item left.
+    pub fn next_is_bytes(&self) -> Result<bool> {
+        match self.input.first() {
+            Some(prefix) => Ok(*prefix < 192),
+            None => Err(self.unexpected(Expected::Item, Found::End))
+        }
+    }
+
+    /// Read the big endian length field of a long form header.
+    fn long_len(&self, be_len: usize, expected: Expected) -> Result<usize> {
+        let len = match (&self.input[1..]).read_uint::<BigEndian>(be_len) {
+            Ok(len) => usize::try_from(len).unwrap_or(usize::MAX),
+            // the length field itself is truncated
+            Err(_) => return Err(self.unexpected(expected, Found::Truncated {
+                declared: 1 + be_len,
+                available: self.input.len()
+            }))
+        };
+        // the length should be minimal and the short form should be 
+        // used whenever possible
+        if self.config.strict && (self.input[1] == 0 || len < 56) {
+            return Err(Error::NonCanonical { offset: self.offset })
+        }
+        Ok(len)
+    }
+
+    /// Parse the header of the next item, the declared length must not run 
+    /// past the input. `expected` is what the caller wants, used for error reporting.
+    fn header(&self, expected: Expected) -> Result<Header> {
+        let Header { list, start, len } = self.parse_header(expected)?;
         let buf = self.input;
-        let (start, end) = match buf[0] {
+        match start.checked_add(len) {
+            Some(end) if end <= buf.len() => Ok(Header { list, start, len }),
+            end => Err(self.unexpected(expected, Found::Truncated {
+                declared: end.unwrap_or(usize::MAX),
+                available: buf.len()
+            }))
+        }
+    }
+
+    /// The length of the next item including its header, only the header 
+    /// has to be in the input. Used by `rlp::from_reader` to know how many 
+    /// bytes to read.
+    pub(crate) fn item_len(&self) -> Result<usize> {
+        let Header { start, len, .. } = self.parse_header(Expected::Item)?;
+        start.checked_add(len).ok_or_else(|| self.unexpected(Expected::Item, Found::Truncated {
+            declared: usize::MAX,
+            available: self.input.len()
+        }))
+    }
+
+    /// Parse the header of the next item without looking at the payload.
+    fn parse_header(&self, expected: Expected) -> Result<Header> {
+        let buf = self.input;
+        let prefix = *buf.first().ok_or_else(|| self.unexpected(expected, Found::End))?;
+        let (list, start, len) = match prefix {
             // R_b(x): ||x|| = 1 \land x[0] \lt 128
-            0..=127 => (0, 1),
+            0..=127 => (false, 0, 1),
             // (128 + ||x||) \dot x
-            len @ 128..=183 => (1, 1 + (len as usize - 128)),
+            len @ 128..=183 => {
+                let len = len as usize - 128;
+                // a single byte below 128 is its own encoding
+                if self.config.strict && len == 1 && buf.get(1).map_or(false, |b| *b < 128) {
+                    return Err(Error::NonCanonical { offset: self.offset })
+                }
+                (false, 1, len)
+            },
             // (183 + ||BE(||x||)||) \dot BE(||x||) \dot x
             be_len @ 184..=191 => {
                 let be_len = be_len as usize - 183;
-                let len = (&buf[1..]).read_uint::<BigEndian>(be_len)
-                    .or(Err(Error::MalformedData))? as usize;
-                (1 + be_len, 1 + be_len + len)
+                (false, 1 + be_len, self.long_len(be_len, expected)?)
             },
-            _ => Err(Error::MalformedData)?
+            // (192 + ||s(x)||) \dot s(x)
+            len @ 192..=247 => (true, 1, len as usize - 192),
+            // (247 + ||BE(||s(x)||)||) \dot BE(||s(x)||) \dot s(x)
+            be_len @ 248..=255 => {
+                let be_len = be_len as usize - 247;
+                (true, 1 + be_len, self.long_len(be_len, expected)?)
+            }
         };
-        Ok((&buf[..end], &buf[start..end], Self::new(&buf[end..])))
+        Ok(Header { list, start, len })
+    }
+
+    /// return value:
+    /// - RLP encoding of the byte slice,
+    /// - the byte slice,
+    /// - the Deserializer for remaining data
+    pub fn next_bytes(&self) -> Result<(&'de [u8], &'de [u8], Self)> {
+        self.next_bytes_as(Expected::Bytes)
+    }
+
+    fn next_bytes_as(&self, expected: Expected) -> Result<(&'de [u8], &'de [u8], Self)> {
+        let Header { list, start, len } = self.header(expected)?;
+        if list {
+            return Err(self.unexpected(expected, Found::List(len)))
+        }
+        if len > self.config.max_string_len {
+            return Err(Error::StringTooLong { offset: self.offset, len, limit: self.config.max_string_len })
+        }
+        let buf = self.input;
+        let end = start + len;
+        Ok((&buf[..end], &buf[start..end], self.child(&buf[end..], self.offset + end)))
     }
 
     /// return value: 
@@ -59,27 +239,141 @@ impl<'de> Deserializer<'de> {
     /// - the deserializer of this sequence
     /// - the deserializer of remaining data.
     pub fn next_seq(&self) -> Result<(&'de [u8], Self, Self)> {
+        self.next_seq_as(Expected::List)
+    }
+
+    fn next_seq_as(&self, expected: Expected) -> Result<(&'de [u8], Self, Self)> {
+        let Header { list, start, len } = self.header(expected)?;
+        if !list {
+            return Err(self.unexpected(expected, Found::Bytes(len)))
+        }
         let buf = self.input;
-        // (192 + ||s(x)||) \dot s(x)
-        let (start, end) = match buf[0] {
-            len @ 192..=247 => (1, 1 + len as usize - 192),
-            be_len @ 248..=255 => {
-                let be_len = be_len as usize - 247;
-                let len = (&buf[1..]).read_uint::<BigEndian>(be_len)
-                    .or(Err(Error::MalformedData))? as usize;
-                (1 + be_len, 1 + be_len + len)
-            },
-            _ => Err(Error::MalformedData)?
+        let end = start + len;
+        Ok((
+            &buf[..end], 
+            self.nested(&buf[start..end], self.offset + start)?, 
+            self.child(&buf[end..], self.offset + end)
+        ))
+    }
+
+    /// Read the next item as a big endian integer of `N` bytes.
+    fn next_be_bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let (_, bytes, new) = self.next_bytes_as(Expected::Integer(N * 8))?;
+        let expanded = be_bytes_expand::<N>(bytes, self.offset)?;
+        *self = new;
+        Ok(expanded)
+    }
+
+    /// Deserialize the next list with `visitor`, `fields` are the field names 
+    /// if it is a struct.
+    fn deserialize_list<V>(&mut self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let (_, mut seq, new) = self.next_seq()?;
+        *self = new;
+        let value = visitor.visit_seq(Seq { de: &mut seq, fields, index: 0 })?;
+        // the visitor may stop early, e.g. a struct with fewer fields than the list
+        if !seq.is_empty() {
+            return Err(Error::TrailingBytes { offset: seq.offset })
+        }
+        Ok(value)
+    }
+
+    /// Read a value without data, which is an empty string or an empty list.
+    /// Only `repr` is accepted in strict mode, see `Config::with_unit_repr`.
+    fn next_empty(&mut self, repr: UnitRepr) -> Result<()> {
+        let expected = match repr {
+            UnitRepr::EmptyString => Expected::EmptyBytes,
+            UnitRepr::EmptyList => Expected::EmptyList
         };
+        let Header { list, start, len } = self.header(expected)?;
+        let found = if list { Found::List(len) } else { Found::Bytes(len) };
+        if len != 0 || (self.config.strict && list != (repr == UnitRepr::EmptyList)) {
+            return Err(self.unexpected(expected, found))
+        }
+        *self = self.child(&self.input[start..], self.offset + start);
+        Ok(())
+    }
+
+    /// Skip the next item without looking into it, 
+    /// returns the deserializer of remaining data.
+    fn skip(&self) -> Result<Self> {
+        if self.next_is_bytes()? {
+            Ok(self.next_bytes()?.2)
+        } else {
+            Ok(self.next_seq()?.2)
+        }
+    }
 
-        Ok((&buf[..end], Self::new(&buf[start..end]), Self::new(&buf[end..])))
+    /// Walk through all remaining items, make sure every nested item 
+    /// is well formed and fits in its parent list.
+    fn validate(mut self) -> Result<()> {
+        let mut count = 0;
+        while !self.is_empty() {
+            self.check_items(count)?;
+            count += 1;
+            self = if self.next_is_bytes()? {
+                self.next_bytes()?.2
+            } else {
+                let (_, seq, remained) = self.next_seq()?;
+                seq.validate()?;
+                remained
+            };
+        }
+        Ok(())
     }
 }
 
-fn be_bytes_expand<const N: usize>(src: &[u8]) -> [u8; N] {
+/// The number of bytes of the length field in a header starting with `prefix`,
+/// which is zero for the short forms.
+pub(crate) fn len_of_len(prefix: u8) -> usize {
+    match prefix {
+        184..=191 => prefix as usize - 183,
+        248..=255 => prefix as usize - 247,
+        _ => 0
+    }
+}
+
+/// Expand a compact big endian integer to `N` bytes. 
+/// Returns `Error::IntegerOverflow` if it does not fit and 
+/// `Error::NonCanonicalInteger` if it has leading zero bytes.
+fn be_bytes_expand<const N: usize>(src: &[u8], offset: usize) -> Result<[u8; N]> {
+    if src.len() > N {
+        return Err(Error::IntegerOverflow { offset, bits: N * 8, len: src.len() })
+    }
+    // zero is encoded as an empty string
+    if src.first() == Some(&0) {
+        return Err(Error::NonCanonicalInteger { offset })
+    }
     let mut dest = [0_u8; N];
     dest[N - src.len()..].copy_from_slice(src);
-    dest
+    Ok(dest)
+}
+
+/// Sign-extend a minimal two's complement big endian integer to `N` bytes,
+/// the same errors as `be_bytes_expand` are reported.
+fn signed_be_bytes_expand<const N: usize>(src: &[u8], offset: usize) -> Result<[u8; N]> {
+    if src.len() > N {
+        return Err(Error::IntegerOverflow { offset, bits: N * 8, len: src.len() })
+    }
+    // the leading byte is redundant if the next byte carries the same sign
+    let redundant = match src {
+        [0x00] => true,
+        [0x00, next, ..] => next & 0x80 == 0,
+        [0xff, next, ..] => next & 0x80 != 0,
+        _ => false
+    };
+    if redundant {
+        return Err(Error::NonCanonicalInteger { offset })
+    }
+    let sign = match src.first() {
+        Some(first) if first & 0x80 != 0 => 0xff,
+        _ => 0x00
+    };
+    let mut dest = [sign; N];
+    dest[N - src.len()..].copy_from_slice(src);
+    Ok(dest)
 }
 
 macro_rules! impl_deseralize_not_supported {
@@ -89,7 +383,7 @@ macro_rules! impl_deseralize_not_supported {
             where
                 V: Visitor<'de>,
             {
-                unimplemented!()
+                Err(Error::TypeNotSupported { type_name: stringify!($ity) })
             }
         )+}
     }
@@ -102,21 +396,50 @@ macro_rules! impl_deseralize_integer {
             where
                 V: Visitor<'de>,
             {
-                let (_, bytes, new) = self.next_bytes()?;
-                let expanded = be_bytes_expand::<{ mem::size_of::<$ity>() }>(bytes);
-                *self = new;
+                let expanded = self.next_be_bytes::<{ mem::size_of::<$ity>() }>()?;
                 visitor.[<visit_ $ity>]($ity::from_be_bytes(expanded))
             }
         )+}
     }
 }
 
+/// Signed integers are decoded according to `config::SignedRepr`.
+macro_rules! impl_deseralize_signed {
+    ($($ity:ident => $uty:ident),+) => {
+        paste! {$(
+            fn [<deserialize_ $ity>]<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                let value = match self.config.signed_repr {
+                    SignedRepr::Unsupported => return Err(Error::TypeNotSupported { type_name: stringify!($ity) }),
+                    SignedRepr::ZigZag => {
+                        let expanded = self.next_be_bytes::<{ mem::size_of::<$uty>() }>()?;
+                        let zigzag = $uty::from_be_bytes(expanded);
+                        ((zigzag >> 1) as $ity) ^ -((zigzag & 1) as $ity)
+                    },
+                    SignedRepr::TwosComplement => {
+                        const N: usize = mem::size_of::<$ity>();
+                        let (_, bytes, new) = self.next_bytes_as(Expected::Integer(N * 8))?;
+                        let expanded = signed_be_bytes_expand::<N>(bytes, self.offset)?;
+                        *self = new;
+                        $ity::from_be_bytes(expanded)
+                    }
+                };
+                visitor.[<visit_ $ity>](value)
+            }
+        )+}
+    }
+}
+
 /// A proxy for more refined manipulation of data when deserializing. 
 /// 
 /// Here is an example about how to use it.
 /// 
 /// ```
-///  #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+/// # use serde::{Serialize, Deserialize};
+/// # use serlp::{de::RlpProxy, rlp::from_bytes};
+/// #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
 /// #[serde(from = "RlpProxy")]
 /// enum Classify {
 ///     Zero(u8),
@@ -127,7 +450,7 @@ macro_rules! impl_deseralize_integer {
 /// impl From<RlpProxy> for Classify {
 ///     fn from(proxy: RlpProxy) -> Self {
 ///         let raw = proxy.raw();
-///         let mut tree = proxy.rlp_tree();
+///         let mut tree = proxy.rlp_tree().unwrap();
 ///         if tree.value_count() == 2 {
 ///             return Classify::Ten(from_bytes(raw).unwrap())
 ///         }
@@ -149,8 +472,18 @@ impl RlpProxy {
         &self.0
     }
 
-    pub fn rlp_tree(&self) -> RlpTree {
-        RlpTree::new(&self.0).unwrap()
+    /// The tree of the raw item, built with the default configuration. 
+    /// The raw item may come from any deserializer, so it is checked again. 
+    /// Use `rlp_tree_with_config` if the proxy was deserialized with a 
+    /// higher depth limit.
+    pub fn rlp_tree(&self) -> Result<RlpTree<'_>> {
+        RlpTree::new(&self.0)
+    }
+
+    /// Same as `rlp_tree`, but with the limits of `config`, 
+    /// see `RlpTree::with_config`.
+    pub fn rlp_tree_with_config(&self, config: Config) -> Result<RlpTree<'_>> {
+        RlpTree::with_config(&self.0, config)
     }
 }
 
@@ -184,18 +517,22 @@ impl<'de> Visitor<'de> for RlpProxyVisitor {
 impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     type Error = Error;
 
-    impl_deseralize_not_supported! {bool, f32, f64, identifier, ignored_any, map, i16, i32, i64, i8}
-    impl_deseralize_integer! {u8, u16, u32, u64}
+    impl_deseralize_not_supported! {f32, f64, identifier, ignored_any}
+    impl_deseralize_integer! {u8, u16, u32, u64, u128}
+    impl_deseralize_signed! {i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128}
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de> 
     {
-        let (bytes, new) = if self.next_is_bytes() {
+        let (bytes, new) = if self.next_is_bytes()? {
             let (bytes, _, new) = self.next_bytes()?;
             (bytes, new)
         } else {
-            let (bytes, _, new) = self.next_seq()?;
+            let (bytes, seq, new) = self.next_seq()?;
+            // the raw item may be parsed again later (e.g. `RlpProxy::rlp_tree`),
+            // so its content must be well formed
+            seq.validate()?;
             (bytes, new)
         };
         
@@ -203,24 +540,36 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_borrowed_bytes(bytes)
     }
 
+    /// Only 0x80 (false) and 0x01 (true) are accepted, like go-ethereum.
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let (_, bytes, new) = self.next_bytes_as(Expected::Bool)?;
+        let value = match bytes {
+            [] => false,
+            [1] => true,
+            _ => return Err(self.unexpected(Expected::Bool, Found::Bytes(bytes.len())))
+        };
+        *self = new;
+        visitor.visit_bool(value)
+    }
+
     // The `Serializer` implementation on the previous page serialized chars as
     // single-character strings so handle that representation here.
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let (_, bytes, new) = self.next_bytes()?;
-        *self = new;
-        let string = String::from_utf8(bytes.to_vec())
-            .or(Err(Error::MalformedData))?;
-        
-        visitor.visit_char(
-            string
-            .as_str()
+        let (_, bytes, new) = self.next_bytes_as(Expected::Char)?;
+        let string = std::str::from_utf8(bytes)
+            .or(Err(self.unexpected(Expected::Char, Found::InvalidUtf8)))?;
+        let ch = string
             .chars()
             .next()
-            .ok_or(Error::MalformedData)?
-        )
+            .ok_or_else(|| self.unexpected(Expected::Char, Found::Bytes(0)))?;
+        *self = new;
+        visitor.visit_char(ch)
     }
 
     // Refer to the "Understanding deserializer lifetimes" page for information
@@ -229,11 +578,10 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let (_, bytes, new) = self.next_bytes()?;
-        *self = new;
+        let (_, bytes, new) = self.next_bytes_as(Expected::Str)?;
         let string = std::str::from_utf8(bytes)
-            .or(Err(Error::MalformedData))?;
-
+            .or(Err(self.unexpected(Expected::Str, Found::InvalidUtf8)))?;
+        *self = new;
         visitor.visit_borrowed_str(string)
     }
 
@@ -262,11 +610,25 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_bytes(visitor)
     }
     
-    fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value>
+    /// An empty string or an empty list is decoded as `None`. Note that 
+    /// this means `Some(0)` and `Some(vec![])` can not survive a round trip.
+    /// 
+    /// If the field is tagged with `types::nil`, `types::nil_string` or 
+    /// `types::nil_list`, only the marker of that field is decoded as `None`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        let is_none = matches!(
+            (self.input.first(), self.nil.take()),
+            (Some(0x80), None | Some(Nil::String)) | (Some(0xc0), None | Some(Nil::List))
+        );
+        if is_none {
+            *self = self.child(&self.input[1..], self.offset + 1);
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
     }
 
     // In Serde, unit means an anonymous value containing no data.
@@ -274,14 +636,8 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-
-        let (_, seq, new) = self.next_seq()?;
-        *self = new;
-        if seq.input.is_empty() {
-            visitor.visit_unit()
-        } else {
-            Err(Error::MalformedData)
-        }
+        self.next_empty(self.config.unit_repr)?;
+        visitor.visit_unit()
     }
 
     // Unit struct means a named value containing no data.
@@ -293,13 +649,8 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let (_, bytes, new) = self.next_bytes()?;
-        *self = new;
-        if bytes.is_empty() {
-            visitor.visit_unit()
-        } else {
-            Err(Error::MalformedData)
-        }
+        self.next_empty(self.config.unit_struct_repr)?;
+        visitor.visit_unit()
     }
 
     // As is done here, serializers are encouraged to treat newtype structs as
@@ -307,25 +658,38 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     // parsing anything other than the contained value.
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.nil = Nil::from_name(name);
         visitor.visit_newtype_struct(self)
     }
 
     // Deserialization of compound types like sequences and maps happens by
     // passing the visitor an "Access" object that gives it the ability to
     // iterate through the data contained in the sequence.
+    //
+    // A byte string is a sequence of `u8` if `Config::with_u8_seq_as_bytes` is set.
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let (_, seq, new) = self.next_seq()?;
-        *self = new;
-        visitor.visit_seq(seq)
+        if self.config.u8_seq_as_bytes && self.next_is_bytes()? {
+            let (encoding, bytes, new) = self.next_bytes()?;
+            let start = self.offset + encoding.len() - bytes.len();
+            let mut seq = ByteSeq { bytes, offset: self.offset, index: 0 };
+            let value = visitor.visit_seq(&mut seq)?;
+            // the visitor may stop early, e.g. an array shorter than the string
+            if seq.index < bytes.len() {
+                return Err(Error::TrailingBytes { offset: start + seq.index })
+            }
+            *self = new;
+            return Ok(value)
+        }
+        self.deserialize_list(&[], visitor)
     }
 
     // Tuples look just like sequences in JSON. Some formats may be able to
@@ -341,7 +705,7 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_seq(visitor)
     }
 
-    // Tuple structs look just like sequences in JSON.
+    // Tuple structs are always lists, see `ser::Serializer::serialize_tuple_struct`.
     fn deserialize_tuple_struct<V>(
         self,
         _name: &'static str,
@@ -351,7 +715,18 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.deserialize_list(&[], visitor)
+    }
+
+    /// Maps are lists of `[key, value]` pairs, see `ser::Serializer::serialize_map`.
+    /// In strict mode the keys must be sorted by their encoding without duplicates.
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let (_, mut seq, new) = self.next_seq()?;
+        *self = new;
+        visitor.visit_map(Map { de: &mut seq, entry: None, key: None, index: 0 })
     }
 
     // Structs look just like maps in JSON.
@@ -363,38 +738,272 @@ impl<'de: 'a, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_struct<V>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.deserialize_list(fields, visitor)
     }
 
+    /// Enums can only be deserialized when the variant index is encoded, 
+    /// see `config::EnumRepr`.
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variants: &'static [&'static str],
-        _visitor: V,
+        visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        let (index, mut payload, new) = match self.config.enum_repr {
+            EnumRepr::Transparent => return Err(Error::TypeNotSupported { type_name: name }),
+            // [index, payload]
+            EnumRepr::Indexed => {
+                let (_, mut seq, new) = self.next_seq()?;
+                let index = u32::deserialize(&mut seq)?;
+                (index, seq, new)
+            },
+            // index || RLP(payload)
+            EnumRepr::TypeByte => {
+                let (span, bytes, new) = self.next_bytes_as(Expected::TypeByte)?;
+                let (index, payload) = bytes.split_first()
+                    .ok_or_else(|| self.unexpected(Expected::TypeByte, Found::Bytes(0)))?;
+                // the payload follows the header and the type byte
+                let offset = self.offset + span.len() - payload.len();
+                (*index as u32, self.nested(payload, offset)?, new)
+            }
+        };
+        *self = new;
+        let value = visitor.visit_enum(Enum { de: &mut payload, index })?;
+        if !payload.is_empty() {
+            return Err(Error::TrailingBytes { offset: payload.offset })
+        }
+        Ok(value)
     }
 }
 
 // `SeqAccess` is provided to the `Visitor` to give it the ability to iterate
 // through elements of the sequence.
-impl<'de, 'a> SeqAccess<'de> for Deserializer<'de> {
+struct Seq<'a, 'de: 'a> {
+    /// the deserializer of the list payload
+    de: &'a mut Deserializer<'de>,
+    /// the field names if the list is a struct, used to build the error path
+    fields: &'static [&'static str],
+    index: usize
+}
+
+impl<'de, 'a> SeqAccess<'de> for Seq<'a, 'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: DeserializeSeed<'de>,
     {
+        // the list payload is used up, this is the end of the list
+        if self.de.is_empty() {
+            return Ok(None)
+        }
+        self.de.check_items(self.index)?;
+        let segment = match self.fields.get(self.index) {
+            Some(name) => Segment::Field(name),
+            None => Segment::Index(self.index)
+        };
+        self.index += 1;
         // Deserialize an array element.
-        seed.deserialize(&mut *self).map(Some)
+        seed.deserialize(&mut *self.de)
+            .map(Some)
+            .map_err(|err| err.nested(segment))
+    }
+
+    /// The number of remaining items, only the headers are walked through.
+    fn size_hint(&self) -> Option<usize> {
+        let mut remained = self.de.child(self.de.input, self.de.offset);
+        let mut count = 0;
+        while !remained.is_empty() {
+            remained = remained.skip().ok()?;
+            count += 1;
+        }
+        Some(count)
+    }
+}
+
+// The elements of a byte string decoded as a sequence of `u8`,
+// see `Config::with_u8_seq_as_bytes`.
+struct ByteSeq<'de> {
+    bytes: &'de [u8],
+    /// the offset of the byte string
+    offset: usize,
+    index: usize
+}
+
+impl<'de> SeqAccess<'de> for ByteSeq<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let byte = match self.bytes.get(self.index) {
+            Some(byte) => *byte,
+            None => return Ok(None)
+        };
+        let segment = Segment::Index(self.index);
+        self.index += 1;
+        seed.deserialize(Byte { byte, offset: self.offset, len: self.bytes.len() })
+            .map(Some)
+            .map_err(|err| err.nested(segment))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.bytes.len() - self.index)
+    }
+}
+
+/// An element of `ByteSeq`, anything but a `u8` expects a list instead.
+struct Byte {
+    byte: u8,
+    /// the offset and the length of the byte string
+    offset: usize,
+    len: usize
+}
+
+impl<'de> de::Deserializer<'de> for Byte {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::MalformedData { offset: self.offset, expected: Expected::List, found: Found::Bytes(self.len) })
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.byte)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+// `MapAccess` is provided to the `Visitor` to give it the ability to iterate
+// through entries of the map.
+struct Map<'a, 'de: 'a> {
+    /// the deserializer of the list of entries
+    de: &'a mut Deserializer<'de>,
+    /// the deserializer of the current `[key, value]` pair
+    entry: Option<Deserializer<'de>>,
+    /// the encoding of the previous key, to check the order in strict mode
+    key: Option<&'de [u8]>,
+    index: usize
+}
+
+impl<'de, 'a> MapAccess<'de> for Map<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.de.is_empty() {
+            return Ok(None)
+        }
+        self.de.check_items(self.index)?;
+        let segment = Segment::Index(self.index);
+        let (_, mut entry, new) = self.de.next_seq().map_err(|err| err.nested(segment))?;
+        *self.de = new;
+
+        let (raw, offset) = (entry.input, entry.offset);
+        let key = seed.deserialize(&mut entry).map_err(|err| err.nested(segment))?;
+        let raw = &raw[..raw.len() - entry.input.len()];
+        if self.de.config.strict && self.key.map_or(false, |prev| prev >= raw) {
+            return Err(Error::NonCanonical { offset }.nested(segment))
+        }
+        self.key = Some(raw);
+        self.entry = Some(entry);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let segment = Segment::Index(self.index);
+        self.index += 1;
+        let mut entry = self.entry.take()
+            .ok_or_else(|| <Error as de::Error>::custom("next_value called before next_key"))?;
+        let value = seed.deserialize(&mut entry).map_err(|err| err.nested(segment))?;
+        if !entry.is_empty() {
+            return Err(Error::TrailingBytes { offset: entry.offset }.nested(segment))
+        }
+        Ok(value)
+    }
+}
+
+/// `EnumAccess` is provided to the `Visitor` to give it the ability to determine
+/// which variant of the enum is supposed to be deserialized.
+struct Enum<'a, 'de: 'a> {
+    /// the deserializer of the payload
+    de: &'a mut Deserializer<'de>,
+    index: u32
+}
+
+impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.index))?;
+        Ok((variant, self))
+    }
+}
+
+// `VariantAccess` is provided to the `Visitor` to give it the ability to see
+// the content of the single variant that it decided to deserialize.
+impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+
+    // Unit variants have no payload, an unexpected payload is 
+    // reported by `deserialize_enum`.
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    // The fields of tuple variants are encoded as a list.
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self.de, len, visitor)
+    }
+
+    // Struct variants are the same as tuple variants.
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_struct(self.de, "", fields, visitor)
     }
 }
@@ -1,13 +1,12 @@
 use std;
 use std::fmt::{self, Display};
+use std::io;
 use serde::{de, ser};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-// This is a bare-bones implementation. A real library would provide additional
-// information in its error type, for example the line and column at which the
-// error occurred, the byte offset into the input, or the current key being
-// processed.
+/// Errors reported while decoding carry the byte offset into the original input
+/// at which the offending item starts.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     // One or more variants that can be created by data structures through the
@@ -17,8 +16,199 @@ pub enum Error {
     // field is missing.
     Message(String),
     UnknownSeqLength,
-    TypeNotSupported,
-    MalformedData
+    /// The type can not be (de)serialized, e.g. floats, or signed integers 
+    /// and enums without an opt-in representation in `config::Config`.
+    TypeNotSupported {
+        type_name: &'static str
+    },
+    /// The data is not what the deserializer expected.
+    MalformedData {
+        offset: usize,
+        expected: Expected,
+        found: Found
+    },
+    /// The data is valid RLP but not in its canonical (minimal) form,
+    /// only reported in strict mode.
+    NonCanonical {
+        offset: usize
+    },
+    /// There are bytes left after the value is decoded.
+    TrailingBytes {
+        offset: usize
+    },
+    /// The integer of `len` bytes is too large for the target type of `bits` bits.
+    IntegerOverflow {
+        offset: usize,
+        bits: usize,
+        len: usize
+    },
+    /// The integer has leading zero bytes.
+    NonCanonicalInteger {
+        offset: usize
+    },
+    /// The item is nested deeper than `limit`, see `config::Config::with_max_depth`.
+    DepthLimitExceeded {
+        offset: usize,
+        limit: usize
+    },
+    /// The input is longer than `limit`, see `config::Config::with_max_input`.
+    InputTooLarge {
+        len: usize,
+        limit: usize
+    },
+    /// The byte string of `len` bytes is longer than `limit`, 
+    /// see `config::Config::with_max_string_len`.
+    StringTooLong {
+        offset: usize,
+        len: usize,
+        limit: usize
+    },
+    /// The list has more items than `limit`, `offset` is where the first 
+    /// item beyond the limit starts. See `config::Config::with_max_list_items`.
+    TooManyItems {
+        offset: usize,
+        limit: usize
+    },
+    /// The input has more items than `limit` when building a tree, 
+    /// see `config::Config::with_max_tree_nodes`.
+    TooManyNodes {
+        offset: usize,
+        limit: usize
+    },
+    /// The encoding of `needed` bytes does not fit in the buffer of 
+    /// `available` bytes, see `rlp::to_slice`.
+    BufferTooSmall {
+        needed: usize,
+        available: usize
+    },
+    /// The underlying reader or writer failed.
+    Io {
+        kind: io::ErrorKind,
+        message: String
+    },
+    /// The error occurred while decoding the field or element at `path`, 
+    /// e.g. `transactions[17].access_list[2]`.
+    Nested {
+        path: Path,
+        error: Box<Error>
+    }
+}
+
+impl Error {
+    /// The byte offset into the original input at which the error occurred,
+    /// only available for errors reported while decoding.
+    pub fn offset(&self) -> Option<usize> {
+        match self.inner() {
+            Error::MalformedData { offset, .. }
+            | Error::NonCanonical { offset }
+            | Error::TrailingBytes { offset }
+            | Error::IntegerOverflow { offset, .. }
+            | Error::NonCanonicalInteger { offset }
+            | Error::DepthLimitExceeded { offset, .. }
+            | Error::StringTooLong { offset, .. }
+            | Error::TooManyItems { offset, .. }
+            | Error::TooManyNodes { offset, .. } => Some(*offset),
+            _ => None
+        }
+    }
+
+    /// The path of the field in which the error occurred, 
+    /// `None` if it occurred at the top level.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Nested { path, .. } => Some(path),
+            _ => None
+        }
+    }
+
+    /// The error without the path.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Nested { error, .. } => error,
+            error => error
+        }
+    }
+
+    /// Prepend a segment to the path of this error, called while the error 
+    /// propagates out of a list.
+    pub(crate) fn nested(self, segment: Segment) -> Error {
+        match self {
+            Error::Nested { mut path, error } => {
+                path.0.insert(0, segment);
+                Error::Nested { path, error }
+            },
+            error => Error::Nested { path: Path(vec![segment]), error: Box::new(error) }
+        }
+    }
+}
+
+/// A segment of `Path`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// a named field of a struct
+    Field(&'static str),
+    /// an element of a sequence, tuple or tuple struct
+    Index(usize)
+}
+
+/// The path from the top level value to a nested field, 
+/// displayed as `block.transactions[17].access_list[2]`.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Path(Vec<Segment>);
+
+impl Path {
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+}
+
+/// What the deserializer expected to see.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// any RLP item
+    Item,
+    /// a byte string
+    Bytes,
+    /// a list
+    List,
+    /// an empty byte string
+    EmptyBytes,
+    /// an empty list
+    EmptyList,
+    /// an unsigned integer of the given width in bits
+    Integer(usize),
+    /// a boolean, i.e. 0x80 or 0x01
+    Bool,
+    /// a single character
+    Char,
+    /// an UTF-8 string
+    Str,
+    /// the type byte of an enum, see `config::EnumRepr::TypeByte`
+    TypeByte
+}
+
+/// What the deserializer found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Found {
+    /// a byte string of the given length
+    Bytes(usize),
+    /// a list with a payload of the given length
+    List(usize),
+    /// the end of input
+    End,
+    /// an item which declares a longer length than available
+    Truncated {
+        declared: usize,
+        available: usize
+    },
+    /// a byte string which is not valid UTF-8
+    InvalidUtf8
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io { kind: err.kind(), message: err.to_string() }
+    }
 }
 
 impl ser::Error for Error {
@@ -33,13 +223,93 @@ impl de::Error for Error {
     }
 }
 
+impl Display for Expected {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Item => formatter.write_str("an RLP item"),
+            Expected::Bytes => formatter.write_str("a byte string"),
+            Expected::List => formatter.write_str("a list"),
+            Expected::EmptyBytes => formatter.write_str("an empty byte string"),
+            Expected::EmptyList => formatter.write_str("an empty list"),
+            Expected::Integer(bits) => write!(formatter, "an integer of {} bits", bits),
+            Expected::Bool => formatter.write_str("a boolean (0x80 or 0x01)"),
+            Expected::Char => formatter.write_str("a character"),
+            Expected::Str => formatter.write_str("an UTF-8 string"),
+            Expected::TypeByte => formatter.write_str("a type byte")
+        }
+    }
+}
+
+impl Display for Found {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Found::Bytes(len) => write!(formatter, "a byte string of {} bytes", len),
+            Found::List(len) => write!(formatter, "a list of {} bytes", len),
+            Found::End => formatter.write_str("the end of input"),
+            Found::Truncated { declared, available } => write!(
+                formatter, "an item of {} bytes with only {} bytes available", declared, available
+            ),
+            Found::InvalidUtf8 => formatter.write_str("invalid UTF-8")
+        }
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Field(name) if i == 0 => formatter.write_str(name)?,
+                Segment::Field(name) => write!(formatter, ".{}", name)?,
+                Segment::Index(index) => write!(formatter, "[{}]", index)?
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Message(msg) => formatter.write_str(msg),
-            Error::TypeNotSupported => formatter.write_str("Type is not supported as it not documented in the yellow paper."),
+            Error::TypeNotSupported { type_name } => write!(
+                formatter, "Type {} is not supported as it not documented in the yellow paper.", type_name
+            ),
             Error::UnknownSeqLength => formatter.write_str("Sequence lenght must be known at compile time."),
-            Error::MalformedData => formatter.write_str("RLP encoded data is malformed.")
+            Error::MalformedData { offset, expected, found } => write!(
+                formatter, "RLP encoded data is malformed at offset {}: expected {}, found {}.", offset, expected, found
+            ),
+            Error::NonCanonical { offset } => write!(
+                formatter, "RLP encoded data is not in canonical form at offset {}.", offset
+            ),
+            Error::TrailingBytes { offset } => write!(
+                formatter, "Trailing bytes after the RLP encoded data at offset {}.", offset
+            ),
+            Error::IntegerOverflow { offset, bits, len } => write!(
+                formatter, "Integer of {} bytes at offset {} is too large for {} bits.", len, offset, bits
+            ),
+            Error::NonCanonicalInteger { offset } => write!(
+                formatter, "Integer at offset {} is not canonical (leading zero bytes).", offset
+            ),
+            Error::DepthLimitExceeded { offset, limit } => write!(
+                formatter, "Item at offset {} is nested deeper than {} levels.", offset, limit
+            ),
+            Error::InputTooLarge { len, limit } => write!(
+                formatter, "Input of {} bytes is larger than the limit of {} bytes.", len, limit
+            ),
+            Error::StringTooLong { offset, len, limit } => write!(
+                formatter, "Byte string of {} bytes at offset {} is longer than the limit of {} bytes.", len, offset, limit
+            ),
+            Error::TooManyItems { offset, limit } => write!(
+                formatter, "List has more than {} items, the first exceeding item is at offset {}.", limit, offset
+            ),
+            Error::TooManyNodes { offset, limit } => write!(
+                formatter, "Input has more than {} items, the first exceeding item is at offset {}.", limit, offset
+            ),
+            Error::BufferTooSmall { needed, available } => write!(
+                formatter, "Buffer of {} bytes is too small for the encoding of {} bytes.", available, needed
+            ),
+            Error::Io { message, .. } => write!(formatter, "I/O error: {}", message),
+            Error::Nested { path, error } => write!(formatter, "{}: {}", path, error)
         }
     }
 }
@@ -2,22 +2,23 @@
 //! 
 //! ### Not Supported Types 
 //! 
-//! - bool
 //! - float numbers
-//! - maps
-//! - enum (only deserialize)
+//! - signed integers (unless a representation is chosen with `config::SignedRepr`)
+//! - enum (only deserialize, by default)
 //! 
-//! We do not support enum when deserializing because we lost some information (i.e. variant //! index) about the original value when serializing.
+//! By default we do not support enum when deserializing because we lost some information (i.e. variant index) about the original value when serializing.
 //! 
 //! We have to choose this approach because there is no enums in Golang while ETH is written in go. Treating enums as a transparent layer can make our furture implementation compatiable with ETH.
 //! 
+//! The variant index can be kept with `config::EnumRepr`, then enums can be deserialized.
+//! 
 //! ### Design principle
 //! 
 //! Accroding to the ETH Yellow Paper, all supported data structure can be represented with either recursive list of byte arrays ![](https://latex.codecogs.com/svg.latex?\mathbb{L}) or byte arrays ![](https://latex.codecogs.com/svg.latex?\mathbb{B}). So we can transform all Rust's compound types, for example, tuple, struct and list, into lists. And then encode them as exactly described in the paper
 //! 
 //! For example, the structure in `test::test_embeded_struct`, can be internally treated as the following form:
 //! 
-//! ```
+//! ```text
 //! [
 //!     "This is a tooooooooooooo loooooooooooooooooooong tag", 
 //!     [
@@ -35,7 +36,7 @@
 //! 
 //! In Rust, we can represent 'empty' in many ways, for example:
 //! 
-//! ```
+//! ```text
 //! [], (), "", b"", struct Empty, Variant::Empty, None, PhantomData<T>
 //! ```
 //! 
@@ -44,10 +45,15 @@
 //! 1. `[]` and `()` are considered empty list, thus should be serialized into 0xc0
 //! 2. All other ZSTs are considered empty, thus should be serialized into 0x80
 //! 
+//! The representation of `()`, unit structs and unit variants can be changed with 
+//! `Config::with_unit_repr`, `Config::with_unit_struct_repr` and `Config::with_unit_variant_repr`.
+//! Either of 0x80 and 0xc0 is accepted when deserializing them, unless in strict mode.
+//! 
 //! To better understand ZSTs' behavior when serializing, try this code:
 //! 
 //! ```rust
-//! #[test]
+//! # use serde::Serialize;
+//! # use serlp::rlp::to_bytes;
 //! fn test_compound_zst() {
 //!     #[derive(Serialize, Debug, PartialEq, Eq)]
 //!     struct ZST;
@@ -80,7 +86,8 @@
 //!     let with_zst_res = to_bytes(&with_zst).unwrap();
 //!     // the container is a list, to this is equivlent to [""]
 //!     assert_eq!(with_zst_res, [0xc1, 0x80]);
-//!     }
+//! }
+//! # test_compound_zst();
 //! ```
 //! 
 //! ### RLP Proxy 
@@ -89,10 +96,11 @@
 //! 
 //! ### (de)serializers for frequently used types
 //! 
-//! We provide two (de)serializers for frequently used types in blockchain.
+//! We provide (de)serializers for frequently used types in blockchain.
 //! 
 //! - `biguint` for `num_bigint::BigUint`
 //! - `byte_array` for `[u8; N]`
+//! - `nil`, `nil_string` and `nil_list` for `Option<T>`, which correspond to `rlp:"nil"`, `rlp:"nilString"` and `rlp:"nilList"` in go-ethereum
 //! 
 //! Put `#[serde(with = "biguint")]` or `#[serde(with = "byte_array")]` before your struct **field** to use them.
 
@@ -102,18 +110,27 @@ pub mod error;
 pub mod rlp;
 pub mod de;
 pub mod types;
+pub mod config;
+
+pub use config::Config;
 
 #[cfg(test)]
 mod test {
     use num_bigint::BigUint;
     use serde::{Serialize, Deserialize};
     use serde_bytes::Bytes;
-    use hex;
 
     use crate::de::RlpProxy;
-    use crate::rlp::to_bytes;
-    use crate::rlp::from_bytes;
-    use crate::types::{biguint, byte_array};
+    use crate::ser::Serializer;
+    use crate::error::{Error, Expected, Found, Segment};
+    use crate::config::{Config, EnumRepr, SignedRepr, UnitRepr, DEFAULT_MAX_READ};
+    use crate::rlp::{to_bytes, to_bytes_with_config, from_bytes_with_config, to_writer, to_writer_with_config, RlpTree};
+    use crate::rlp::{encoded_len, encoded_len_with_config, to_slice, to_slice_with_config};
+    use crate::rlp::{from_bytes, from_bytes_strict, from_bytes_prefix, from_reader, from_reader_with_config};
+    use crate::types::{biguint, byte_array, nil, nil_list, nil_string};
+
+    /// The set theoretical definition of three, `[ [], [[]], [ [], [[]] ] ]`.
+    type SetThree = ((), ((),), ((), ((),)));
 
     /// The transcation is the #0 transcation of 
     /// https://api.etherscan.io/api?module=proxy&action=eth_getBlockByNumber&tag=0xa1a489&boolean=true&apikey=YourApiKeyToken
@@ -179,7 +196,7 @@ mod test {
         impl From<RlpProxy> for Classify {
             fn from(proxy: RlpProxy) -> Self {
                 let raw = proxy.raw();
-                let mut tree = proxy.rlp_tree();
+                let mut tree = proxy.rlp_tree().unwrap();
                 if tree.value_count() == 2 {
                     return Classify::Ten(from_bytes(raw).unwrap())
                 }
@@ -225,7 +242,7 @@ mod test {
         let three = Three(((), ((),), ((), ((),))));
 
         let three_expected = [0xc7, 0xc0, 0xc1, 0xc0, 0xc3, 0xc0, 0xc1, 0xc0];
-        let origin: Three<((), ((),), ((), ((),)))> = from_bytes(&three_expected).unwrap();
+        let origin: Three<_> = from_bytes(&three_expected).unwrap();
         assert_eq!(to_bytes(&three).unwrap(), three_expected);
         assert_eq!(origin, three)
     }
@@ -330,10 +347,10 @@ mod test {
     fn test_boxed_value() {
         #[derive(Serialize, Debug, PartialEq, Eq, Deserialize, Clone)]
         struct Boxed {
-            a: Box<String>
+            a: Box<str>
         }
 
-        let b = Boxed { a: Box::new("dog".into()) };
+        let b = Boxed { a: "dog".into() };
         let expected = [0xc4, 0x83, b'd', b'o', b'g'];
         let origin: Boxed = from_bytes(&expected).unwrap();
 
@@ -401,33 +418,33 @@ mod test {
     #[test]
     fn test_compound_zst() {
         #[derive(Serialize, Debug, PartialEq, Eq)]
-        struct ZST;
+        struct Zst;
 
         #[derive(Serialize, Debug, PartialEq, Eq)]
         enum Simple {
-            Empty(ZST),
+            Empty(Zst),
             #[allow(dead_code)]
             Int((u32, u64))
         }
 
         #[derive(Serialize, Debug, PartialEq, Eq)]
-        struct ContainZST(Simple);
+        struct ContainZst(Simple);
 
         #[derive(Serialize, Debug, PartialEq, Eq)]
-        struct StructZST {
+        struct StructZst {
             zst: Simple
         }
 
-        let zst = Simple::Empty(ZST);
+        let zst = Simple::Empty(Zst);
         let zst_res = to_bytes(&zst).unwrap();
         assert_eq!(zst_res, [0x80]);
 
-        let with_zst = ContainZST(Simple::Empty(ZST));
+        let with_zst = ContainZst(Simple::Empty(Zst));
         let with_zst_res = to_bytes(&with_zst).unwrap();
         // the container is transparent because its a newtype
         assert_eq!(with_zst_res, [0x80]);
         
-        let with_zst = StructZST { zst: Simple::Empty(ZST) };
+        let with_zst = StructZst { zst: Simple::Empty(Zst) };
         let with_zst_res = to_bytes(&with_zst).unwrap();
         // the container is a list, to this is equivlent to [""]
         assert_eq!(with_zst_res, [0xc1, 0x80]);
@@ -450,7 +467,7 @@ mod test {
         struct Embedded {
             time: u64,
             out: (u8, u32),
-            three: Third<((), ((),), ((), ((),)))>
+            three: Third<SetThree>
         }
 
         #[derive(Serialize, Debug, PartialEq, Eq)]
@@ -462,9 +479,6 @@ mod test {
             Struct(Embeding<'a>)
         }
 
-        #[derive(Serialize, Debug, PartialEq, Eq)]
-        struct Equiv((u32, u64));
-
         let embed = Embeding {
             tag: "This is a tooooooooooooo loooooooooooooooooooong tag",
             ed: Embedded {
@@ -488,25 +502,25 @@ mod test {
     #[test]
     fn test_zst_struct() {
         #[derive(Serialize, Debug, PartialEq, Eq, Deserialize)]
-        struct ZST;
+        struct Zst;
 
         #[derive(Serialize, Debug, PartialEq, Eq, Deserialize)]
-        struct WithZST {
+        struct WithZst {
             f1: u8,
-            f2: ZST,
+            f2: Zst,
             f3: (),
             f4: u8,
         }
 
-        let zst = WithZST {
+        let zst = WithZst {
             f1: 1,
-            f2: ZST,
+            f2: Zst,
             f3: (),
             f4: 4
         };
 
         let encoded = to_bytes(&zst).unwrap();
-        let origin: WithZST = from_bytes(&encoded).unwrap();
+        let origin: WithZst = from_bytes(&encoded).unwrap();
         let expected = [0xc4, 0x1, 0x80, 0xc0, 0x4];
         
         assert_eq!(origin, zst);
@@ -530,7 +544,7 @@ mod test {
         struct Embedded {
             time: u64,
             out: (u8, u32),
-            three: Third<((), ((),), ((), ((),)))>
+            three: Third<SetThree>
         }
 
         let embed = Embeding {
@@ -550,5 +564,1381 @@ mod test {
         assert_eq!(embed, origin);
     }
 
-}
+    #[test]
+    fn test_strict_canonical() {
+        #[derive(Serialize, Debug, PartialEq, Eq, Deserialize)]
+        struct Int(u8);
+
+        // a single byte below 0x80 must not be wrapped
+        let wrapped = [0x81, 0x05];
+        assert_eq!(from_bytes::<Int>(&wrapped).unwrap(), Int(5));
+        assert_eq!(from_bytes_strict::<Int>(&wrapped), Err(Error::NonCanonical { offset: 0 }));
+        assert_eq!(from_bytes_strict::<Int>(&[0x81, 0x80]).unwrap(), Int(0x80));
+
+        // long form header for a short string
+        let long_str = [0xb8, 0x03, b'd', b'o', b'g'];
+        assert_eq!(from_bytes::<&str>(&long_str).unwrap(), "dog");
+        assert_eq!(from_bytes_strict::<&str>(&long_str), Err(Error::NonCanonical { offset: 0 }));
+
+        // length field with leading zeros
+        let mut zero_len = vec![0xb9, 0x00, 0x38];
+        zero_len.extend([b'a'; 0x38]);
+        assert_eq!(from_bytes::<&str>(&zero_len).unwrap().len(), 0x38);
+        assert_eq!(from_bytes_strict::<&str>(&zero_len), Err(Error::NonCanonical { offset: 0 }));
+
+        // long form header for a short list
+        let long_list = [0xf8, 0x02, 0x01, 0x02];
+        assert_eq!(from_bytes::<(u8, u8)>(&long_list).unwrap(), (1, 2));
+        assert_eq!(from_bytes_strict::<(u8, u8)>(&long_list), Err(Error::NonCanonical { offset: 0 }));
+
+        // non-canonical data nested in a list
+        let nested = [0xc3, 0x01, 0x81, 0x02];
+        assert_eq!(from_bytes_strict::<(u8, u8)>(&nested).unwrap_err().inner(), &Error::NonCanonical { offset: 2 });
+        assert!(RlpTree::new(&nested).is_ok());
+        assert_eq!(RlpTree::new_strict(&nested), Err(Error::NonCanonical { offset: 2 }));
+
+        // canonical long forms are accepted
+        let mut long_str = vec![0xb8, 0x38];
+        long_str.extend([b'a'; 0x38]);
+        assert_eq!(from_bytes_strict::<&str>(&long_str).unwrap().len(), 0x38);
+        assert!(RlpTree::new_strict(&long_str).is_ok());
+    }
+
+    /// A tiny xorshift generator, so the fuzz tests are deterministic 
+    /// and need no extra dependency.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn byte(&mut self) -> u8 {
+            self.next() as u8
+        }
+
+        fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+            let len = self.next() as usize % (max_len + 1);
+            (0..len).map(|_| self.byte()).collect()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct Hostile<'a> {
+        nonce: u64,
+        tag: &'a str,
+        ch: char,
+        pair: (u8, u16),
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        #[serde(with = "biguint")]
+        value: BigUint,
+        #[serde(with = "byte_array")]
+        to: [u8; 4],
+        three: ((), ((),), ((), ((),))),
+        name: String
+    }
+
+    fn hostile_sample() -> Vec<u8> {
+        to_bytes(&Hostile {
+            nonce: 0x1234_5678,
+            tag: "tag",
+            ch: '哼',
+            pair: (0x7f, 0x8000),
+            data: vec![0xc0; 60],
+            value: BigUint::from(1_u8) << 100_u32,
+            to: [0xde, 0xad, 0xbe, 0xef],
+            three: ((), ((),), ((), ((),))),
+            name: "Lorem ipsum dolor sit amet, consectetur adipisicing elit".into()
+        }).unwrap()
+    }
+
+    /// Feed the input to every decode path, none of them may panic.
+    fn decode_all(input: &[u8]) {
+        let _ = from_bytes::<Hostile>(input);
+        let _ = from_bytes::<u64>(input);
+        let _ = from_bytes::<u8>(input);
+        let _ = from_bytes::<char>(input);
+        let _ = from_bytes::<&str>(input);
+        let _ = from_bytes::<&Bytes>(input);
+        let _ = from_bytes::<()>(input);
+        let _ = from_bytes::<(u8, (u16, u32), ())>(input);
+        let _ = from_bytes_strict::<Hostile>(input);
+        if let Ok(proxy) = from_bytes::<RlpProxy>(input) {
+            proxy.rlp_tree().unwrap().for_each(drop);
+        }
+        let config = Config::new()
+            .with_strict(true)
+            .with_max_depth(1000)
+            .with_enum_repr(EnumRepr::Indexed)
+            .with_signed_repr(SignedRepr::ZigZag)
+            .with_u8_seq_as_bytes(true);
+        let _ = from_bytes_with_config::<Hostile>(input, config);
+        let _ = from_bytes_with_config::<(Vec<u8>, [u8; 4], Vec<u16>)>(input, config);
+        if let Ok(proxy) = from_bytes_with_config::<RlpProxy>(input, config) {
+            proxy.rlp_tree_with_config(config).unwrap().for_each(drop);
+            let _ = proxy.rlp_tree();
+        }
+        let _ = RlpTree::with_config(input, config);
+        if let Ok(tree) = RlpTree::new(input) {
+            tree.for_each(drop);
+        }
+        let _ = RlpTree::new_strict(input);
+    }
+
+    #[test]
+    fn test_hostile_input() {
+        // empty input
+        assert_eq!(from_bytes::<u8>(&[]), Err(Error::MalformedData { offset: 0, expected: Expected::Integer(8), found: Found::End }));
+        assert_eq!(RlpTree::new(&[]), Err(Error::MalformedData { offset: 0, expected: Expected::Item, found: Found::End }));
+        // declared string length runs past the buffer
+        assert_eq!(from_bytes::<&str>(&[0x83, b'd', b'o']), Err(Error::MalformedData { offset: 0, expected: Expected::Str, found: Found::Truncated { declared: 4, available: 3 } }));
+        // missing length field
+        assert_eq!(from_bytes::<&str>(&[0xb8]), Err(Error::MalformedData { offset: 0, expected: Expected::Str, found: Found::Truncated { declared: 2, available: 1 } }));
+        // huge length field
+        let huge = [0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+        assert_eq!(from_bytes::<&str>(&huge), Err(Error::MalformedData { offset: 0, expected: Expected::Str, found: Found::Truncated { declared: usize::MAX, available: 10 } }));
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+        assert_eq!(from_bytes::<(u8,)>(&huge), Err(Error::MalformedData { offset: 0, expected: Expected::List, found: Found::Truncated { declared: usize::MAX, available: 10 } }));
+        // child overruns the parent list
+        let overrun = [0xc2, 0x83, b'd', b'o', b'g'];
+        assert_eq!(from_bytes::<(&str,)>(&overrun).unwrap_err().inner(), &Error::MalformedData { offset: 1, expected: Expected::Str, found: Found::Truncated { declared: 4, available: 2 } });
+        assert_eq!(RlpTree::new(&overrun), Err(Error::MalformedData { offset: 1, expected: Expected::Bytes, found: Found::Truncated { declared: 4, available: 2 } }));
+        assert_eq!(from_bytes::<RlpProxy>(&overrun), Err(Error::MalformedData { offset: 1, expected: Expected::Bytes, found: Found::Truncated { declared: 4, available: 2 } }));
+        // too few items in the list
+        assert!(from_bytes::<(u8, u8)>(&[0xc1, 0x01]).is_err());
+        // integer wider than the target type
+        assert_eq!(from_bytes::<u16>(&[0x83, 0x01, 0x02, 0x03]), Err(Error::IntegerOverflow { offset: 0, bits: 16, len: 3 }));
+    }
+
+    #[test]
+    fn test_fuzz_truncated() {
+        let encoded = hostile_sample();
+        assert!(from_bytes::<Hostile>(&encoded).is_ok());
+        for end in 0..encoded.len() {
+            decode_all(&encoded[..end]);
+            assert!(from_bytes::<Hostile>(&encoded[..end]).is_err());
+            assert!(RlpTree::new(&encoded[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_fuzz_mutated() {
+        let encoded = hostile_sample();
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20000 {
+            let mut mutated = encoded.clone();
+            for _ in 0..1 + rng.next() % 4 {
+                let pos = rng.next() as usize % mutated.len();
+                mutated[pos] = rng.byte();
+            }
+            decode_all(&mutated);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_random() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20000 {
+            let mut input = rng.bytes(48);
+            // bias towards list prefixes so nested paths are reached
+            if let Some(first) = input.first_mut() {
+                if rng.next() % 2 == 0 {
+                    *first |= 0xc0;
+                }
+            }
+            decode_all(&input);
+        }
+    }
+
+    #[test]
+    fn test_trailing_bytes() {
+        let input = [0x01, 0xff];
+        assert_eq!(from_bytes::<u8>(&input), Err(Error::TrailingBytes { offset: 1 }));
+        assert_eq!(from_bytes_strict::<u8>(&input), Err(Error::TrailingBytes { offset: 1 }));
+        assert_eq!(RlpTree::new(&input), Err(Error::TrailingBytes { offset: 1 }));
+
+        let (value, remained) = from_bytes_prefix::<u8>(&input).unwrap();
+        assert_eq!(value, 1);
+        assert_eq!(remained, [0xff]);
+
+        // two concatenated items
+        let mut input = to_bytes(&(1_u8, "dog")).unwrap();
+        input.extend(to_bytes(&"cat").unwrap());
+        let (first, remained) = from_bytes_prefix::<(u8, &str)>(&input).unwrap();
+        let (second, remained) = from_bytes_prefix::<&str>(remained).unwrap();
+        assert_eq!(first, (1, "dog"));
+        assert_eq!(second, "cat");
+        assert!(remained.is_empty());
+    }
+
+    #[test]
+    fn test_collections() {
+        use std::collections::{VecDeque, HashSet, BTreeSet};
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct AccessItem {
+            #[serde(with = "byte_array")]
+            address: [u8; 4],
+            storage_keys: Vec<u64>
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Block {
+            txs: Vec<AccessItem>,
+            uncles: Vec<Vec<String>>,
+            queue: VecDeque<u32>,
+            set: HashSet<u16>,
+            sorted: BTreeSet<u64>
+        }
+
+        let block = Block {
+            txs: vec![
+                AccessItem { address: [1, 2, 3, 4], storage_keys: vec![0, 1, 1024, u64::MAX] },
+                AccessItem { address: [0xff; 4], storage_keys: vec![] }
+            ],
+            uncles: vec![vec![], vec!["dog".into(), "cat".into()]],
+            queue: VecDeque::from(vec![1, 2, 3]),
+            set: [7, 0x8000].into_iter().collect(),
+            sorted: (0..100).collect()
+        };
+
+        let encoded = to_bytes(&block).unwrap();
+        assert_eq!(from_bytes::<Block>(&encoded).unwrap(), block);
+
+        let empty: Vec<u8> = vec![];
+        assert_eq!(to_bytes(&empty).unwrap(), [0xc0]);
+        assert_eq!(from_bytes::<Vec<u8>>(&[0xc0]).unwrap(), empty);
+        assert_eq!(from_bytes::<Vec<u8>>(&[0xc3, 0x01, 0x02, 0x03]).unwrap(), [1, 2, 3]);
+
+        // the list contains more items than the struct has fields
+        assert_eq!(from_bytes::<(u8, u8)>(&[0xc3, 0x01, 0x02, 0x03]), Err(Error::TrailingBytes { offset: 3 }));
+    }
+
+    #[test]
+    fn test_seq_size_hint() {
+        struct Hint;
+
+        impl<'de> serde::de::Visitor<'de> for Hint {
+            type Value = Option<usize>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a list")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>
+            {
+                let hint = seq.size_hint();
+                while seq.next_element::<RlpProxy>()?.is_some() {}
+                Ok(hint)
+            }
+        }
+
+        struct SizeHint(Option<usize>);
+
+        impl<'de> Deserialize<'de> for SizeHint {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>
+            {
+                deserializer.deserialize_seq(Hint).map(SizeHint)
+            }
+        }
+
+        let encoded = to_bytes(&(1_u8, "dog", ((), ()), vec![0_u64; 30])).unwrap();
+        assert_eq!(from_bytes::<SizeHint>(&encoded).unwrap().0, Some(4));
+        assert_eq!(from_bytes::<SizeHint>(&[0xc0]).unwrap().0, Some(0));
+    }
+
+    #[test]
+    fn test_option() {
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+        struct Address(#[serde(with = "byte_array")] [u8; 4]);
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+        struct Inner {
+            a: u8,
+            b: String
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Optional {
+            // contract creation
+            #[serde(with = "nil")]
+            to: Option<Address>,
+            #[serde(with = "nil")]
+            inner: Option<Inner>,
+            #[serde(with = "nil_list")]
+            list: Option<u8>,
+            #[serde(with = "nil_string")]
+            string: Option<Vec<u8>>,
+            bare: Option<Option<u64>>
+        }
+
+        let none = Optional { to: None, inner: None, list: None, string: None, bare: None };
+        let encoded = to_bytes(&none).unwrap();
+        assert_eq!(encoded, [0xc5, 0x80, 0xc0, 0xc0, 0x80, 0x80]);
+        assert_eq!(from_bytes::<Optional>(&encoded).unwrap(), none);
+
+        let some = Optional {
+            to: Some(Address([1, 2, 3, 4])),
+            inner: Some(Inner { a: 1, b: "dog".into() }),
+            list: Some(0),
+            string: Some(vec![1, 2]),
+            bare: Some(Some(1024))
+        };
+        let encoded = to_bytes(&some).unwrap();
+        assert_eq!(from_bytes::<Optional>(&encoded).unwrap(), some);
+
+        // nil_list only takes an empty list as None, so Some(0) survives
+        assert_eq!(encoded[12], 0x80);
+        // nil only takes the marker of the kind of T as None
+        assert!(from_bytes::<Optional>(&[0xc5, 0xc0, 0xc0, 0xc0, 0x80, 0x80]).is_err());
+        assert!(from_bytes::<Optional>(&[0xc5, 0x80, 0x80, 0xc0, 0x80, 0x80]).is_err());
+
+        #[derive(Serialize, Debug)]
+        enum Payload {
+            #[allow(dead_code)]
+            Legacy(u64),
+            Typed(Vec<u64>)
+        }
+
+        impl Default for Payload {
+            fn default() -> Self {
+                Payload::Typed(Vec::new())
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Envelope {
+            #[serde(serialize_with = "nil::serialize")]
+            payload: Option<Payload>
+        }
+
+        // the kind is the one of T::default()
+        assert_eq!(to_bytes(&Envelope { payload: None }).unwrap(), [0xc1, 0xc0]);
+        assert_eq!(encoded_len(&Envelope { payload: None }).unwrap(), 2);
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct Logs(Vec<u64>);
+
+        // nil needs neither Serialize nor Default to decode
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct Receipt {
+            #[serde(deserialize_with = "nil::deserialize")]
+            logs: Option<Logs>
+        }
+
+        assert_eq!(from_bytes::<Receipt>(&[0xc1, 0xc0]).unwrap(), Receipt { logs: None });
+        assert_eq!(from_bytes::<Receipt>(&[0xc3, 0xc2, 0x01, 0x02]).unwrap(), Receipt { logs: Some(Logs(vec![1, 2])) });
+        assert!(from_bytes::<Receipt>(&[0xc1, 0x80]).is_err());
+        // a bare option takes both markers as None
+        assert_eq!(from_bytes::<Option<u8>>(&[0x80]).unwrap(), None);
+        assert_eq!(from_bytes::<Option<u8>>(&[0xc0]).unwrap(), None);
+        assert_eq!(from_bytes::<Option<u8>>(&[0x05]).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_optional_trailing_fields() {
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Header {
+            number: u64,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            base_fee: Option<u64>
+        }
+
+        let legacy = Header { number: 1, base_fee: None };
+        let encoded = to_bytes(&legacy).unwrap();
+        assert_eq!(encoded, [0xc1, 0x01]);
+        assert_eq!(from_bytes::<Header>(&encoded).unwrap(), legacy);
+
+        let london = Header { number: 1, base_fee: Some(7) };
+        let encoded = to_bytes(&london).unwrap();
+        assert_eq!(encoded, [0xc2, 0x01, 0x07]);
+        assert_eq!(from_bytes::<Header>(&encoded).unwrap(), london);
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Rect(u32, u32),
+        Named { name: String, sides: Vec<u8> }
+    }
+
+    /// Every kind of variant, including payloads which need long form headers.
+    fn shapes() -> Vec<Shape> {
+        vec![
+            Shape::Empty, 
+            Shape::Circle(0), 
+            Shape::Rect(1024, 1), 
+            Shape::Named { name: "tooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooo long".into(), sides: vec![3; 300] }
+        ]
+    }
+
+    #[test]
+    fn test_enum_indexed() {
+        let config = Config::new().with_enum_repr(EnumRepr::Indexed);
+        let encode = |v: &Shape| to_bytes_with_config(v, config).unwrap();
+
+        assert_eq!(encode(&Shape::Empty), [0xc1, 0x80]);
+        assert_eq!(encode(&Shape::Circle(5)), [0xc2, 0x01, 0x05]);
+        assert_eq!(encode(&Shape::Rect(3, 4)), [0xc4, 0x02, 0xc2, 0x03, 0x04]);
+        assert_eq!(
+            encode(&Shape::Named { name: "dog".into(), sides: vec![1] }), 
+            [0xc8, 0x03, 0xc6, 0x83, b'd', b'o', b'g', 0xc1, 0x01]
+        );
+
+        let shapes = shapes();
+        let encoded = to_bytes_with_config(&shapes, config).unwrap();
+        assert_eq!(from_bytes_with_config::<Vec<Shape>>(&encoded, config).unwrap(), shapes);
+
+        // unknown variant index and unexpected payload
+        assert!(from_bytes_with_config::<Shape>(&[0xc1, 0x04], config).is_err());
+        assert_eq!(from_bytes_with_config::<Shape>(&[0xc2, 0x80, 0x01], config), Err(Error::TrailingBytes { offset: 2 }));
+    }
+
+    #[test]
+    fn test_enum_type_byte() {
+        let config = Config::new().with_enum_repr(EnumRepr::TypeByte);
+        let encode = |v: &Shape| to_bytes_with_config(v, config).unwrap();
+
+        assert_eq!(encode(&Shape::Empty), [0x00]);
+        assert_eq!(encode(&Shape::Circle(5)), [0x82, 0x01, 0x05]);
+        assert_eq!(encode(&Shape::Rect(3, 4)), [0x84, 0x02, 0xc2, 0x03, 0x04]);
+
+        let shapes = shapes();
+        let encoded = to_bytes_with_config(&shapes, config).unwrap();
+        assert_eq!(from_bytes_with_config::<Vec<Shape>>(&encoded, config).unwrap(), shapes);
+
+        // missing type byte
+        assert_eq!(from_bytes_with_config::<Shape>(&[0x80], config), Err(Error::MalformedData { offset: 0, expected: Expected::TypeByte, found: Found::Bytes(0) }));
+    }
+
+    #[test]
+    fn test_enum_transparent() {
+        // the default representation drops the variant index
+        assert_eq!(to_bytes(&Shape::Circle(5)).unwrap(), [0x05]);
+        assert_eq!(from_bytes::<Shape>(&[0x05]), Err(Error::TypeNotSupported { type_name: "Shape" }));
+    }
+
+    #[test]
+    fn test_integer_canonical() {
+        assert_eq!(from_bytes::<u64>(&[0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).unwrap(), u64::MAX);
+        assert_eq!(from_bytes::<u64>(&[0x89, 0x01, 0, 0, 0, 0, 0, 0, 0, 0]), Err(Error::IntegerOverflow { offset: 0, bits: 64, len: 9 }));
+        assert_eq!(from_bytes::<u8>(&[0x82, 0x01, 0x00]), Err(Error::IntegerOverflow { offset: 0, bits: 8, len: 2 }));
+
+        // zero must be encoded as an empty string
+        assert_eq!(from_bytes::<u32>(&[0x80]).unwrap(), 0);
+        assert_eq!(from_bytes::<u32>(&[0x00]), Err(Error::NonCanonicalInteger { offset: 0 }));
+        assert_eq!(from_bytes::<u32>(&[0x82, 0x00, 0x01]), Err(Error::NonCanonicalInteger { offset: 0 }));
+        assert_eq!(from_bytes::<u16>(&[0x82, 0x00, 0x80]), Err(Error::NonCanonicalInteger { offset: 0 }));
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Big(#[serde(with = "biguint")] BigUint);
+        assert_eq!(from_bytes::<Big>(&[0x80]).unwrap(), Big(BigUint::from(0_u8)));
+        assert_eq!(from_bytes::<Big>(&[0x82, 0x01, 0x00]).unwrap(), Big(BigUint::from(256_u16)));
+        assert!(from_bytes::<Big>(&[0x00]).is_err());
+        assert!(from_bytes::<Big>(&[0x82, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_128() {
+        let max = to_bytes(&u128::MAX).unwrap();
+        assert_eq!(max, [&[0x90][..], &[0xff; 16]].concat());
+        assert_eq!(from_bytes::<u128>(&max).unwrap(), u128::MAX);
+        assert_eq!(to_bytes(&1024_u128).unwrap(), [0x82, 0x04, 0x00]);
+        assert_eq!(from_bytes::<u128>(&[0x82, 0x04, 0x00]).unwrap(), 1024);
+        assert_eq!(from_bytes::<u128>(&[&[0x91, 0x01][..], &[0; 16]].concat()), Err(Error::IntegerOverflow { offset: 0, bits: 128, len: 17 }));
+
+        // signed integers are rejected by default
+        assert_eq!(to_bytes(&-1_i128), Err(Error::TypeNotSupported { type_name: "i128" }));
+        assert_eq!(from_bytes::<i128>(&[0x01]), Err(Error::TypeNotSupported { type_name: "i128" }));
+
+        let config = Config::new().with_signed_repr(SignedRepr::ZigZag);
+        let encode = |v: i128| to_bytes_with_config(&v, config).unwrap();
+        assert_eq!(encode(0), [0x80]);
+        assert_eq!(encode(-1), [0x01]);
+        assert_eq!(encode(1), [0x02]);
+        assert_eq!(encode(-65), [0x81, 0x81]);
+        for v in [0, 1, -1, 63, -64, 64, i64::MIN as i128, i128::MAX, i128::MIN] {
+            assert_eq!(from_bytes_with_config::<i128>(&encode(v), config).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn test_signed() {
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Deltas {
+            a: i8,
+            b: i16,
+            c: i32,
+            d: i64
+        }
+
+        // the yellow paper default keeps rejecting signed integers
+        let deltas = Deltas { a: -1, b: 300, c: i32::MIN, d: -129 };
+        assert_eq!(to_bytes(&deltas), Err(Error::TypeNotSupported { type_name: "i8" }));
+        assert_eq!(from_bytes::<i32>(&[0x01]), Err(Error::TypeNotSupported { type_name: "i32" }));
+
+        let zigzag = Config::new().with_signed_repr(SignedRepr::ZigZag);
+        let twos = Config::new().with_signed_repr(SignedRepr::TwosComplement);
+        for config in [zigzag, twos] {
+            let encoded = to_bytes_with_config(&deltas, config).unwrap();
+            assert_eq!(from_bytes_with_config::<Deltas>(&encoded, config).unwrap(), deltas);
+            for v in [0, 1, -1, 127, -128, 128, -129, i64::MAX, i64::MIN] {
+                let encoded = to_bytes_with_config(&v, config).unwrap();
+                assert_eq!(from_bytes_with_config::<i64>(&encoded, config).unwrap(), v);
+            }
+            for v in [0, 1, -1, i8::MAX, i8::MIN] {
+                let encoded = to_bytes_with_config(&v, config).unwrap();
+                assert_eq!(from_bytes_with_config::<i8>(&encoded, config).unwrap(), v);
+            }
+        }
+
+        assert_eq!(to_bytes_with_config(&-1_i16, zigzag).unwrap(), [0x01]);
+        assert_eq!(to_bytes_with_config(&-129_i16, zigzag).unwrap(), [0x82, 0x01, 0x01]);
+
+        let encode = |v: i32| to_bytes_with_config(&v, twos).unwrap();
+        assert_eq!(encode(0), [0x80]);
+        assert_eq!(encode(127), [0x7f]);
+        assert_eq!(encode(128), [0x82, 0x00, 0x80]);
+        assert_eq!(encode(-1), [0x81, 0xff]);
+        assert_eq!(encode(-128), [0x81, 0x80]);
+        assert_eq!(encode(-129), [0x82, 0xff, 0x7f]);
+        assert_eq!(encode(i32::MIN), [0x84, 0x80, 0x00, 0x00, 0x00]);
+
+        // overflow and redundant sign bytes
+        assert_eq!(from_bytes_with_config::<i8>(&[0x82, 0x00, 0x80], twos), Err(Error::IntegerOverflow { offset: 0, bits: 8, len: 2 }));
+        assert_eq!(from_bytes_with_config::<i16>(&[0x00], twos), Err(Error::NonCanonicalInteger { offset: 0 }));
+        assert_eq!(from_bytes_with_config::<i16>(&[0x82, 0x00, 0x7f], twos), Err(Error::NonCanonicalInteger { offset: 0 }));
+        assert_eq!(from_bytes_with_config::<i16>(&[0x82, 0xff, 0x80], twos), Err(Error::NonCanonicalInteger { offset: 0 }));
+    }
+
+    #[test]
+    fn test_bool() {
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct Flags {
+            a: bool,
+            b: bool
+        }
+
+        assert_eq!(to_bytes(&false).unwrap(), [0x80]);
+        assert_eq!(to_bytes(&true).unwrap(), [0x01]);
+        let flags = Flags { a: true, b: false };
+        let encoded = to_bytes(&flags).unwrap();
+        assert_eq!(encoded, [0xc2, 0x01, 0x80]);
+        assert_eq!(from_bytes::<Flags>(&encoded).unwrap(), flags);
+
+        // everything else is rejected
+        assert_eq!(from_bytes::<bool>(&[0x00]), Err(Error::MalformedData { offset: 0, expected: Expected::Bool, found: Found::Bytes(1) }));
+        assert_eq!(from_bytes::<bool>(&[0x02]), Err(Error::MalformedData { offset: 0, expected: Expected::Bool, found: Found::Bytes(1) }));
+        assert_eq!(from_bytes::<bool>(&[0x82, 0x00, 0x01]), Err(Error::MalformedData { offset: 0, expected: Expected::Bool, found: Found::Bytes(2) }));
+        assert_eq!(from_bytes::<bool>(&[0xc0]), Err(Error::MalformedData { offset: 0, expected: Expected::Bool, found: Found::List(0) }));
+    }
+
+    #[test]
+    fn test_error_offset() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Header {
+            number: u64,
+            extra: Vec<u8>
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Block {
+            headers: Vec<Header>,
+            nonce: u32
+        }
+
+        let block = Block {
+            headers: vec![
+                Header { number: 1, extra: vec![1, 2] },
+                Header { number: 0x1234, extra: vec![] }
+            ],
+            nonce: 7
+        };
+        let encoded = to_bytes(&block).unwrap();
+        assert_eq!(from_bytes::<Block>(&encoded).unwrap(), block);
+
+        // the second header's number starts at offset 8 and does not fit in u8
+        assert_eq!(&encoded[8..11], [0x82, 0x12, 0x34]);
+        let err = from_bytes::<(Vec<(u8, Vec<u8>)>, u32)>(&encoded).unwrap_err();
+        assert_eq!(err.inner(), &Error::IntegerOverflow { offset: 8, bits: 8, len: 2 });
+        assert_eq!(err.offset(), Some(8));
+
+        // a list where an integer is expected
+        let mut mismatch = encoded.clone();
+        mismatch[12] = 0xc0;
+        let err = from_bytes::<Block>(&mismatch).unwrap_err();
+        assert_eq!(err.inner(), &Error::MalformedData { offset: 12, expected: Expected::Integer(32), found: Found::List(0) });
+        assert_eq!(err.offset(), Some(12));
+        assert_eq!(
+            err.to_string(), 
+            "nonce: RLP encoded data is malformed at offset 12: expected an integer of 32 bits, found a list of 0 bytes."
+        );
+
+        // truncated in the middle of the second header
+        let err = from_bytes::<Block>(&encoded[..10]).unwrap_err();
+        assert_eq!(err, Error::MalformedData { offset: 0, expected: Expected::List, found: Found::Truncated { declared: 13, available: 10 } });
+    }
+
+    #[test]
+    fn test_error_path() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct AccessItem {
+            address: u64,
+            storage_keys: Vec<u64>
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Tx {
+            nonce: u64,
+            access_list: Vec<AccessItem>
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Block {
+            number: u64,
+            transactions: Vec<Tx>
+        }
+
+        /// `Tx` whose storage keys only have 8 bits
+        type NarrowTx = (u64, Vec<(u64, Vec<u8>)>);
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Narrow {
+            number: u64,
+            transactions: Vec<NarrowTx>
+        }
+
+        let tx = |keys: Vec<u64>| Tx { 
+            nonce: 1, 
+            access_list: vec![AccessItem { address: 0, storage_keys: vec![] }, AccessItem { address: 1, storage_keys: keys }] 
+        };
+        let block = Block { number: 7, transactions: vec![tx(vec![]), tx(vec![1, 2, 0x100])] };
+        let encoded = to_bytes(&block).unwrap();
+
+        let err = from_bytes::<Narrow>(&encoded).unwrap_err();
+        assert_eq!(err.path().unwrap().to_string(), "transactions[1][1][1][1][2]");
+        assert!(matches!(err.inner(), Error::IntegerOverflow { bits: 8, len: 2, .. }));
+
+        // field names are used for structs
+        let mut corrupted = encoded.clone();
+        let last = corrupted.len() - 3;
+        assert_eq!(corrupted[last..], [0x82, 0x01, 0x00]);
+        corrupted[last] = 0xc2;
+        let err = from_bytes::<Block>(&corrupted).unwrap_err();
+        assert_eq!(
+            err.path().unwrap().segments(), 
+            [
+                Segment::Field("transactions"), 
+                Segment::Index(1), 
+                Segment::Field("access_list"), 
+                Segment::Index(1), 
+                Segment::Field("storage_keys"), 
+                Segment::Index(2)
+            ]
+        );
+        assert_eq!(err.path().unwrap().to_string(), "transactions[1].access_list[1].storage_keys[2]");
+        assert_eq!(err.offset(), Some(last));
+
+        // errors at the top level have no path
+        assert_eq!(from_bytes::<Block>(&[0x01]).unwrap_err().path(), None);
+    }
+
+    #[test]
+    fn test_type_not_supported() {
+        use serde::de::IgnoredAny;
+
+        assert_eq!(to_bytes(&1.5_f32), Err(Error::TypeNotSupported { type_name: "f32" }));
+        assert_eq!(to_bytes(&1.5_f64), Err(Error::TypeNotSupported { type_name: "f64" }));
+        assert_eq!(from_bytes::<f32>(&[0x01]), Err(Error::TypeNotSupported { type_name: "f32" }));
+        assert_eq!(from_bytes::<f64>(&[0x01]), Err(Error::TypeNotSupported { type_name: "f64" }));
+        assert_eq!(from_bytes::<IgnoredAny>(&[0x01]), Err(Error::TypeNotSupported { type_name: "ignored_any" }));
+
+        // nested errors are recoverable as well
+        let err = from_bytes::<(u8, f64)>(&[0xc2, 0x01, 0x02]).unwrap_err();
+        assert_eq!(err.inner(), &Error::TypeNotSupported { type_name: "f64" });
+        assert_eq!(err.path().unwrap().to_string(), "[1]");
+    }
+
+    #[test]
+    fn test_max_depth() {
+        use serde::de::value::BorrowedBytesDeserializer;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Node(Vec<Node>);
+
+        let mut node = Node(vec![]);
+        for _ in 0..1000 {
+            node = Node(vec![node]);
+        }
+        let deep = to_bytes(&node).unwrap();
+        assert!(matches!(RlpTree::new(&deep), Err(Error::DepthLimitExceeded { limit: 128, .. })));
+        assert!(matches!(from_bytes::<RlpProxy>(&deep), Err(Error::DepthLimitExceeded { limit: 128, .. })));
+        assert!(matches!(from_bytes::<Node>(&deep).unwrap_err().inner(), Error::DepthLimitExceeded { limit: 128, .. }));
+        // a proxy accepted with a higher limit is turned into a tree with the same limit
+        let mut node = Node(vec![]);
+        for _ in 0..200 {
+            node = Node(vec![node]);
+        }
+        let config = Config::new().with_max_depth(1000);
+        let proxy = from_bytes_with_config::<RlpProxy>(&to_bytes(&node).unwrap(), config).unwrap();
+        assert!(matches!(proxy.rlp_tree(), Err(Error::DepthLimitExceeded { limit: 128, .. })));
+        assert_eq!(proxy.rlp_tree_with_config(config).unwrap().value_count(), 0);
+
+        // a proxy from another deserializer holds bytes which were never 
+        // checked, they must not overflow the stack either
+        let depth = 300_000;
+        let header = |len: usize| -> Vec<u8> {
+            if len < 56 {
+                return vec![0xc0 + len as u8]
+            }
+            let be = len.to_be_bytes();
+            let be = &be[be.iter().position(|byte| *byte != 0).unwrap()..];
+            [&[0xf7 + be.len() as u8], be].concat()
+        };
+        // the length of each level, from the innermost one
+        let mut lens = vec![1];
+        for i in 0..depth {
+            lens.push(lens[i] + header(lens[i]).len());
+        }
+        let mut hostile = Vec::with_capacity(lens[depth]);
+        for i in (0..depth).rev() {
+            hostile.extend(header(lens[i]));
+        }
+        hostile.push(0xc0);
+        let proxy = RlpProxy::deserialize(BorrowedBytesDeserializer::<Error>::new(&hostile)).unwrap();
+        assert!(matches!(proxy.rlp_tree(), Err(Error::DepthLimitExceeded { limit: 128, .. })));
+
+        // the limit is configurable
+        let config = Config::new().with_max_depth(3);
+        let node = Node(vec![Node(vec![Node(vec![])])]);
+        let encoded = to_bytes(&node).unwrap();
+        assert_eq!(encoded, [0xc2, 0xc1, 0xc0]);
+        assert_eq!(from_bytes_with_config::<Node>(&encoded, config).unwrap(), node);
+        assert!(RlpTree::with_config(&encoded, config).is_ok());
+        let config = Config::new().with_max_depth(2);
+        let limit = Error::DepthLimitExceeded { offset: 2, limit: 2 };
+        assert_eq!(from_bytes_with_config::<Node>(&encoded, config).unwrap_err().inner(), &limit);
+        assert_eq!(RlpTree::with_config(&encoded, config), Err(limit));
+
+        // nested type byte payloads count as well
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        enum Chain {
+            End,
+            Link(Box<Chain>)
+        }
+        let config = Config::new().with_enum_repr(EnumRepr::TypeByte).with_max_depth(2);
+        let chain = Chain::Link(Box::new(Chain::Link(Box::new(Chain::End))));
+        let encoded = to_bytes_with_config(&chain, config).unwrap();
+        assert!(matches!(from_bytes_with_config::<Chain>(&encoded, config), Err(Error::DepthLimitExceeded { limit: 2, .. })));
+        let config = config.with_max_depth(3);
+        assert_eq!(from_bytes_with_config::<Chain>(&encoded, config).unwrap(), chain);
+    }
+
+    #[test]
+    fn test_limits() {
+        let data: (Vec<u8>, &str) = (vec![1, 2, 3], "dog");
+        let encoded = to_bytes(&data).unwrap();
+        assert_eq!(encoded, [0xc8, 0xc3, 0x01, 0x02, 0x03, 0x83, b'd', b'o', b'g']);
+        let decode = |config: Config| from_bytes_with_config::<(Vec<u8>, &str)>(&encoded, config);
+        let tree = |config: Config| RlpTree::with_config(&encoded, config).map(|_| ());
+
+        // exactly at the limits
+        let config = Config::new()
+            .with_max_input(9)
+            .with_max_string_len(3)
+            .with_max_list_items(3)
+            .with_max_tree_nodes(6);
+        assert_eq!(decode(config).unwrap(), data);
+        assert_eq!(tree(config), Ok(()));
+
+        let config = Config::new().with_max_input(8);
+        assert_eq!(decode(config), Err(Error::InputTooLarge { len: 9, limit: 8 }));
+        assert_eq!(tree(config), Err(Error::InputTooLarge { len: 9, limit: 8 }));
+
+        let config = Config::new().with_max_string_len(2);
+        assert_eq!(decode(config).unwrap_err().inner(), &Error::StringTooLong { offset: 5, len: 3, limit: 2 });
+        assert_eq!(tree(config), Err(Error::StringTooLong { offset: 5, len: 3, limit: 2 }));
+
+        let config = Config::new().with_max_list_items(2);
+        assert_eq!(decode(config).unwrap_err().inner(), &Error::TooManyItems { offset: 4, limit: 2 });
+        assert_eq!(tree(config), Err(Error::TooManyItems { offset: 4, limit: 2 }));
+        assert_eq!(from_bytes_with_config::<RlpProxy>(&encoded, config), Err(Error::TooManyItems { offset: 4, limit: 2 }));
+
+        let config = Config::new().with_max_tree_nodes(5);
+        assert_eq!(tree(config), Err(Error::TooManyNodes { offset: 5, limit: 5 }));
+        // only the tree is limited by the number of nodes
+        assert_eq!(decode(config).unwrap(), data);
+    }
+
+    #[test]
+    fn test_to_writer() {
+        use std::io;
+
+        let shapes = shapes();
+        for repr in [EnumRepr::Transparent, EnumRepr::Indexed, EnumRepr::TypeByte] {
+            let config = Config::new().with_enum_repr(repr);
+            let mut buf = Vec::new();
+            to_writer_with_config(&mut buf, &shapes, config).unwrap();
+            assert_eq!(buf, to_bytes_with_config(&shapes, config).unwrap());
+        }
+
+        let nested = (vec![vec![(); 100]; 3], Some("dog"), None::<u64>, u128::MAX);
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &nested).unwrap();
+        assert_eq!(buf, to_bytes(&nested).unwrap());
+
+        // errors of the writer are reported
+        struct Full(usize);
+        impl io::Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(io::ErrorKind::WriteZero.into())
+                }
+                let len = buf.len().min(self.0);
+                self.0 -= len;
+                Ok(len)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(to_writer(Full(1024), &nested).is_ok());
+        assert!(matches!(to_writer(Full(10), &nested), Err(Error::Io { kind: io::ErrorKind::WriteZero, .. })));
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        fn check<T: Serialize>(value: &T, config: Config) {
+            assert_eq!(encoded_len_with_config(value, config).unwrap(), to_bytes_with_config(value, config).unwrap().len());
+        }
+
+        let config = Config::new();
+        check(&0_u8, config);
+        check(&0x7f_u8, config);
+        check(&0x80_u8, config);
+        check(&u128::MAX, config);
+        check(&"a".repeat(55), config);
+        check(&"a".repeat(56), config);
+        check(&"a".repeat(1 << 16), config);
+        check(&vec![1_u8; 55], config);
+        check(&vec![1_u8; 56], config);
+        check(&vec![vec![vec![0x80_u64; 20]; 20]; 20], config);
+        check(&(Some(()), None::<u8>, 'c', true), config);
+
+        let shapes = shapes();
+        for repr in [EnumRepr::Transparent, EnumRepr::Indexed, EnumRepr::TypeByte] {
+            check(&shapes, config.with_enum_repr(repr));
+        }
+
+        assert_eq!(encoded_len(&"dog").unwrap(), 4);
+        assert_eq!(encoded_len(&-1_i8), Err(Error::TypeNotSupported { type_name: "i8" }));
+    }
+
+    #[test]
+    fn test_long_headers() {
+        // the short form header of the longest short list
+        assert_eq!(to_bytes(&vec![0_u8; 55]).unwrap()[..2], [0xf7, 0x80]);
+        // the shortest long form header
+        let encoded = to_bytes(&vec![0_u8; 56]).unwrap();
+        assert_eq!(encoded.len(), 58);
+        assert_eq!(encoded[..3], [0xf8, 0x38, 0x80]);
+        assert_eq!(*encoded.last().unwrap(), 0x80);
+
+        // nested long lists
+        let nested = vec![vec![0_u8; 56]; 2];
+        let encoded = to_bytes(&nested).unwrap();
+        assert_eq!(encoded[..5], [0xf8, 0x74, 0xf8, 0x38, 0x80]);
+        assert_eq!(encoded[60..63], [0xf8, 0x38, 0x80]);
+        assert_eq!(from_bytes::<Vec<Vec<u8>>>(&encoded).unwrap(), nested);
+
+        let nested = (vec![vec!["a".repeat(300); 300]; 3], vec![(); 100], "b".repeat(70_000));
+        let encoded = to_bytes(&nested).unwrap();
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &nested).unwrap();
+        assert_eq!(encoded, buf);
+        assert_eq!(encoded.capacity(), encoded.len());
+        assert_eq!(from_bytes::<(Vec<Vec<String>>, Vec<()>, String)>(&encoded).unwrap(), nested);
+    }
+
+    #[test]
+    fn test_deep_nesting_linear() {
+        use std::io;
+
+        #[derive(Serialize)]
+        enum Tree {
+            Leaf(&'static str),
+            Node(Vec<Tree>)
+        }
+
+        // every level has a payload of 56 bytes or more, so every header 
+        // is in the long form
+        fn nested(depth: usize) -> Tree {
+            (0..depth).fold(Tree::Leaf("a leaf which is long enough for a long form header......"), |tree, _| Tree::Node(vec![tree]))
+        }
+
+        /// Counts the bytes and the calls it receives.
+        #[derive(Default)]
+        struct Counter {
+            bytes: usize,
+            writes: usize
+        }
+
+        impl io::Write for Counter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.bytes += buf.len();
+                self.writes += 1;
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // deep values need a larger stack in debug builds
+        std::thread::Builder::new().stack_size(256 << 20).spawn(|| {
+            for depth in [1000, 2000, 4000, 8000] {
+                let tree = nested(depth);
+                let encoded = to_bytes(&tree).unwrap();
+                assert_eq!(encoded.capacity(), encoded.len());
+
+                // `to_bytes` shares the second pass with `to_writer`: every byte 
+                // is written exactly once and a header is written at its final 
+                // place, with one write per header and one for the leaf
+                let mut counter = Counter::default();
+                to_writer(&mut counter, &tree).unwrap();
+                assert_eq!(counter.bytes, encoded.len());
+                assert_eq!(counter.writes, depth + 2);
+
+                let mut buf = Vec::new();
+                to_writer(&mut buf, &tree).unwrap();
+                assert_eq!(buf, encoded);
+            }
+            // the nested values are dropped recursively
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_to_slice() {
+        #[derive(Serialize)]
+        struct Account {
+            nonce: u64,
+            balance: u128,
+            storage_root: (u64, u64, u64, u64),
+            code_hash: &'static str
+        }
+
+        let account = Account { 
+            nonce: 1, 
+            balance: 10_u128.pow(20), 
+            storage_root: (u64::MAX, 0, 1, 2), 
+            code_hash: "the hash of the code, which is longer than 56 bytes......" 
+        };
+        let expected = to_bytes(&account).unwrap();
+        assert!(expected.len() > 56 + 2);
+
+        let mut buf = [0_u8; 128];
+        let len = to_slice(&account, &mut buf).unwrap();
+        assert_eq!(buf[..len], expected);
+
+        // exactly fits
+        let mut buf = vec![0_u8; expected.len()];
+        assert_eq!(to_slice(&account, &mut buf), Ok(expected.len()));
+        assert_eq!(buf, expected);
+
+        // every shorter buffer is rejected, including the one without room 
+        // for the long form header
+        for available in 0..expected.len() {
+            let mut buf = vec![0_u8; available];
+            assert_eq!(to_slice(&account, &mut buf), Err(Error::BufferTooSmall { needed: expected.len(), available }));
+        }
+
+        let config = Config::new().with_enum_repr(EnumRepr::TypeByte);
+        let shape = Shape::Named { name: "triangle".into(), sides: vec![3, 4, 5] };
+        let mut buf = [0_u8; 32];
+        let len = to_slice_with_config(&shape, &mut buf, config).unwrap();
+        assert_eq!(buf[..len], to_bytes_with_config(&shape, config).unwrap());
+    }
+
+    #[test]
+    fn test_reuse_serializer() {
+        let mut serializer = Serializer::new();
+        serializer.serialize_into(&("cat", "dog")).unwrap();
+        serializer.serialize_into(&vec![1_u64, 2, 3]).unwrap();
+        let mut expected = to_bytes(&("cat", "dog")).unwrap();
+        expected.extend(to_bytes(&vec![1_u64, 2, 3]).unwrap());
+        assert_eq!(serializer.as_bytes(), expected);
+
+        // a failed value leaves the buffer untouched
+        assert_eq!(serializer.serialize_into(&(1_u8, -1_i8)), Err(Error::TypeNotSupported { type_name: "i8" }));
+        assert_eq!(serializer.as_bytes(), expected);
+
+        // the allocation is kept across messages
+        let long = vec!["a long message which needs a long form header"; 8];
+        serializer.clear();
+        serializer.serialize_into(&long).unwrap();
+        let buffer = serializer.as_bytes().as_ptr();
+        for _ in 0..4 {
+            serializer.clear();
+            serializer.serialize_into(&long).unwrap();
+            assert_eq!(serializer.as_bytes(), to_bytes(&long).unwrap());
+            assert_eq!(serializer.as_bytes().as_ptr(), buffer);
+        }
+        assert_eq!(serializer.into_inner(), to_bytes(&long).unwrap());
+
+        let config = Config::new().with_signed_repr(SignedRepr::ZigZag);
+        let mut serializer = Serializer::with_config(config);
+        serializer.serialize_into(&-1_i64).unwrap();
+        assert_eq!(serializer.into_inner(), [0x01]);
+    }
+
+    #[test]
+    fn test_map() {
+        use std::collections::{BTreeMap, HashMap};
+
+        let map: BTreeMap<String, u64> = [("dog", 1), ("cat", 2), ("horse", 300)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
+        let encoded = to_bytes(&map).unwrap();
+        // sorted by the encoding of the keys, shorter keys first
+        let expected = to_bytes(&(("cat", 2_u64), ("dog", 1_u64), ("horse", 300_u64))).unwrap();
+        assert_eq!(encoded, expected);
+        assert_eq!(from_bytes::<BTreeMap<String, u64>>(&encoded).unwrap(), map);
+        assert_eq!(from_bytes_strict::<BTreeMap<String, u64>>(&encoded).unwrap(), map);
+
+        // the output does not depend on the iteration order
+        let hash_map: HashMap<String, u64> = map.clone().into_iter().collect();
+        assert_eq!(to_bytes(&hash_map).unwrap(), encoded);
+        assert_eq!(encoded_len(&hash_map).unwrap(), encoded.len());
+        assert_eq!(from_bytes::<HashMap<String, u64>>(&encoded).unwrap(), hash_map);
+
+        let empty: BTreeMap<u8, u8> = BTreeMap::new();
+        assert_eq!(to_bytes(&empty).unwrap(), [0xc0]);
+        assert_eq!(from_bytes::<BTreeMap<u8, u8>>(&[0xc0]).unwrap(), empty);
+
+        let nested: BTreeMap<u32, Vec<BTreeMap<u8, String>>> = [(1, vec![[(2, "two".to_string())].into()]), (0, vec![])].into();
+        assert_eq!(from_bytes::<BTreeMap<u32, Vec<BTreeMap<u8, String>>>>(&to_bytes(&nested).unwrap()).unwrap(), nested);
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &nested).unwrap();
+        assert_eq!(buf, to_bytes(&nested).unwrap());
+
+        // unsorted and duplicate keys are only rejected in strict mode
+        let unsorted = to_bytes(&((2_u8, 0_u8), (1_u8, 0_u8))).unwrap();
+        assert_eq!(from_bytes::<BTreeMap<u8, u8>>(&unsorted).unwrap(), [(1, 0), (2, 0)].into());
+        let err = from_bytes_strict::<BTreeMap<u8, u8>>(&unsorted).unwrap_err();
+        assert_eq!(err.inner(), &Error::NonCanonical { offset: 5 });
+        assert_eq!(err.path().unwrap().to_string(), "[1]");
+        let duplicate = to_bytes(&((1_u8, 0_u8), (1_u8, 0_u8))).unwrap();
+        assert!(matches!(from_bytes_strict::<BTreeMap<u8, u8>>(&duplicate).unwrap_err().inner(), Error::NonCanonical { .. }));
+
+        // every entry is exactly a [key, value] pair
+        assert!(matches!(
+            from_bytes::<BTreeMap<u8, u8>>(&[0xc2, 0xc1, 0x01]).unwrap_err().inner(), 
+            Error::MalformedData { found: Found::End, .. }
+        ));
+        assert_eq!(
+            from_bytes::<BTreeMap<u8, u8>>(&[0xc4, 0xc3, 0x01, 0x02, 0x03]).unwrap_err().inner(), 
+            &Error::TrailingBytes { offset: 4 }
+        );
+        assert!(from_bytes::<BTreeMap<u8, u8>>(&[0xc1, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_nested_map() {
+        use std::cell::Cell;
+        use std::collections::BTreeMap;
+
+        thread_local! {
+            static KEYS: Cell<usize> = Cell::new(0);
+        }
+
+        /// Counts how many times it is serialized
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Key(u8);
+
+        impl Serialize for Key {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                KEYS.with(|keys| keys.set(keys.get() + 1));
+                self.0.serialize(serializer)
+            }
+        }
+
+        #[derive(Serialize)]
+        struct M(BTreeMap<Key, M>);
+
+        const DEPTH: u8 = 20;
+        let mut m = M(BTreeMap::new());
+        for i in 0..DEPTH {
+            m = M([(Key(i), m), (Key(200), M(BTreeMap::new()))].into_iter().collect());
+        }
+        let count = |encode: &mut dyn FnMut()| {
+            KEYS.with(|keys| keys.set(0));
+            encode();
+            KEYS.with(|keys| keys.get())
+        };
 
+        // every entry is encoded once, however deep the maps are nested
+        let entries = 2 * DEPTH as usize;
+        let mut encoded = Vec::new();
+        assert_eq!(count(&mut || encoded = to_bytes(&m).unwrap()), entries);
+        let mut buf = Vec::new();
+        assert_eq!(count(&mut || to_writer(&mut buf, &m).unwrap()), entries);
+        assert_eq!(buf, encoded);
+        let mut slice = vec![0; encoded.len()];
+        assert_eq!(count(&mut || assert_eq!(to_slice(&m, &mut slice).unwrap(), encoded.len())), entries);
+        assert_eq!(slice, encoded);
+        assert_eq!(count(&mut || assert_eq!(encoded_len(&m).unwrap(), encoded.len())), entries);
+    }
+
+    #[test]
+    fn test_u8_seq_as_bytes() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Header {
+            parent_hash: [u8; 32],
+            extra: Vec<u8>,
+            bloom: Vec<[u8; 4]>,
+            numbers: Vec<u64>,
+            logs: Vec<String>
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Rgb(u8, u8, u8);
+
+        let config = Config::new().with_u8_seq_as_bytes(true);
+        let header = Header {
+            parent_hash: [0xab; 32],
+            extra: b"hello".to_vec(),
+            bloom: vec![[1, 2, 3, 4], [0; 4]],
+            numbers: vec![1, 1024],
+            logs: vec![]
+        };
+        let encoded = to_bytes_with_config(&header, config).unwrap();
+        let expected = to_bytes(&(
+            Bytes::new(&[0xab; 32]), 
+            Bytes::new(b"hello"), 
+            vec![Bytes::new(&[1, 2, 3, 4]), Bytes::new(&[0; 4])], 
+            vec![1_u64, 1024],
+            ()
+        )).unwrap();
+        assert_eq!(encoded, expected);
+        assert_eq!(from_bytes_with_config::<Header>(&encoded, config).unwrap(), header);
+        // the default still encodes them as lists, and does not decode byte strings
+        assert_ne!(to_bytes(&header).unwrap(), encoded);
+        assert!(from_bytes::<Header>(&encoded).is_err());
+        assert_eq!(encoded_len_with_config(&header, config).unwrap(), encoded.len());
+        let mut buf = Vec::new();
+        to_writer_with_config(&mut buf, &header, config).unwrap();
+        assert_eq!(buf, encoded);
+
+        assert_eq!(to_bytes_with_config(&vec![0x05_u8], config).unwrap(), [0x05]);
+        assert_eq!(to_bytes_with_config(&vec![0x80_u8], config).unwrap(), [0x81, 0x80]);
+        // serde does not tell arrays from tuples
+        assert_eq!(to_bytes_with_config(&(1_u8, 2_u8), config).unwrap(), [0x82, 0x01, 0x02]);
+        // empty sequences have no element to tell their type, they are still lists
+        assert_eq!(to_bytes_with_config(&Vec::<u8>::new(), config).unwrap(), [0xc0]);
+        assert_eq!(to_bytes_with_config(&(1_u8, Vec::<(u64, u64)>::new()), config).unwrap(), [0xc2, 0x01, 0xc0]);
+        assert_eq!(from_bytes_with_config::<Vec<u8>>(&[0xc0], config).unwrap(), Vec::<u8>::new());
+        assert_eq!(from_bytes_with_config::<Vec<u8>>(&[0x80], config).unwrap(), Vec::<u8>::new());
+
+        // tuple structs, tuples with other elements and sequences of other types are lists
+        assert_eq!(to_bytes_with_config(&Rgb(1, 2, 3), config).unwrap(), [0xc3, 0x01, 0x02, 0x03]);
+        assert_eq!(to_bytes_with_config(&Rgb(1, 2, 3), config.with_strict(true)).unwrap(), [0xc3, 0x01, 0x02, 0x03]);
+        assert_eq!(from_bytes_with_config::<Rgb>(&[0xc3, 0x01, 0x02, 0x03], config).unwrap(), Rgb(1, 2, 3));
+        assert!(from_bytes_with_config::<Rgb>(&[0x83, 0x01, 0x02, 0x03], config).is_err());
+        assert_eq!(to_bytes_with_config(&(1_u8, 2_u16), config).unwrap(), [0xc2, 0x01, 0x02]);
+        assert_eq!(to_bytes_with_config(&vec![1_u16, 2], config).unwrap(), [0xc2, 0x01, 0x02]);
+
+        // lists of bytes are still accepted, byte strings only for u8 elements
+        assert_eq!(from_bytes_with_config::<[u8; 2]>(&[0xc2, 0x01, 0x02], config).unwrap(), [1, 2]);
+        assert_eq!(
+            from_bytes_with_config::<[u8; 2]>(&[0x83, 0x01, 0x02, 0x03], config).unwrap_err().inner(),
+            &Error::TrailingBytes { offset: 3 }
+        );
+        assert!(from_bytes_with_config::<[u8; 4]>(&[0x83, 0x01, 0x02, 0x03], config).is_err());
+        let err = from_bytes_with_config::<Vec<u16>>(&[0x82, 0x01, 0x02], config).unwrap_err();
+        assert_eq!(err.inner(), &Error::MalformedData { offset: 0, expected: Expected::List, found: Found::Bytes(2) });
+        assert!(from_bytes_with_config::<[u16; 2]>(&[0x82, 0x01, 0x02], config).is_err());
+        assert!(from_bytes::<Vec<u8>>(&[0x82, 0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_unit_repr() {
+        use std::marker::PhantomData;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Marker;
+
+        #[derive(Serialize, Debug, PartialEq, Eq)]
+        enum Simple {
+            Empty,
+            #[allow(dead_code)]
+            Int(u8)
+        }
+
+        // the defaults
+        assert_eq!(to_bytes(&()).unwrap(), [0xc0]);
+        assert_eq!(to_bytes(&Marker).unwrap(), [0x80]);
+        assert_eq!(to_bytes(&PhantomData::<u8>).unwrap(), [0x80]);
+        assert_eq!(to_bytes(&Simple::Empty).unwrap(), [0x80]);
+
+        let config = Config::new()
+            .with_unit_repr(UnitRepr::EmptyString)
+            .with_unit_struct_repr(UnitRepr::EmptyList)
+            .with_unit_variant_repr(UnitRepr::EmptyList);
+        assert_eq!(to_bytes_with_config(&(), config).unwrap(), [0x80]);
+        assert_eq!(to_bytes_with_config(&Marker, config).unwrap(), [0xc0]);
+        assert_eq!(to_bytes_with_config(&PhantomData::<u8>, config).unwrap(), [0xc0]);
+        assert_eq!(to_bytes_with_config(&Simple::Empty, config).unwrap(), [0xc0]);
+        assert_eq!(to_bytes_with_config(&((), Marker), config).unwrap(), [0xc2, 0x80, 0xc0]);
+
+        // both representations are accepted unless in strict mode
+        for input in [[0x80], [0xc0]] {
+            assert_eq!(from_bytes::<()>(&input), Ok(()));
+            assert_eq!(from_bytes::<Marker>(&input), Ok(Marker));
+            assert_eq!(from_bytes_with_config::<PhantomData<u8>>(&input, config), Ok(PhantomData));
+        }
+        assert_eq!(from_bytes_strict::<()>(&[0xc0]), Ok(()));
+        assert_eq!(
+            from_bytes_strict::<()>(&[0x80]), 
+            Err(Error::MalformedData { offset: 0, expected: Expected::EmptyList, found: Found::Bytes(0) })
+        );
+        assert_eq!(from_bytes_strict::<Marker>(&[0x80]), Ok(Marker));
+        let strict = config.with_strict(true);
+        assert_eq!(from_bytes_with_config::<((), Marker)>(&[0xc2, 0x80, 0xc0], strict), Ok(((), Marker)));
+        assert!(from_bytes_with_config::<((), Marker)>(&[0xc2, 0xc0, 0x80], strict).is_err());
+
+        // only empty items are units
+        assert_eq!(
+            from_bytes::<()>(&[0x00]), 
+            Err(Error::MalformedData { offset: 0, expected: Expected::EmptyList, found: Found::Bytes(1) })
+        );
+        assert_eq!(
+            from_bytes::<Marker>(&[0xc1, 0xc0]), 
+            Err(Error::MalformedData { offset: 0, expected: Expected::EmptyBytes, found: Found::List(1) })
+        );
+    }
+
+    #[test]
+    fn test_config_profile() {
+        use crate::Config;
+        use crate::types::nil_string;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Receipt {
+            status: Option<u8>,
+            #[serde(with = "nil_string")]
+            contract: Option<u8>,
+            logs: Vec<Shape>,
+            gas: i64
+        }
+
+        const PROFILE: Config = Config::new()
+            .with_strict(true)
+            .with_enum_repr(EnumRepr::Indexed)
+            .with_signed_repr(SignedRepr::TwosComplement)
+            .with_none_repr(UnitRepr::EmptyList)
+            .with_max_depth(8);
+
+        let receipt = Receipt { 
+            status: None, 
+            contract: None, 
+            logs: vec![Shape::Circle(3)], 
+            gas: -1
+        };
+        let encoded = PROFILE.serialize(&receipt).unwrap();
+        assert_eq!(encoded, to_bytes_with_config(&receipt, PROFILE).unwrap());
+        // the tagged field overrides the configured representation of `None`
+        assert_eq!(encoded[..3], [0xc8, 0xc0, 0x80]);
+        assert_eq!(PROFILE.serialized_size(&receipt).unwrap(), encoded.len());
+        assert_eq!(PROFILE.deserialize::<Receipt>(&encoded).unwrap(), receipt);
+
+        // the defaults are unchanged
+        assert_eq!(Config::new(), Config::default());
+        assert_eq!(Config::new().serialize(&receipt.status).unwrap(), to_bytes(&receipt.status).unwrap());
+        assert!(Config::new().serialize(&receipt).is_err());
+        assert_eq!(
+            PROFILE.with_max_depth(1).deserialize::<Receipt>(&encoded).unwrap_err().inner(), 
+            &Error::DepthLimitExceeded { offset: 3, limit: 1 }
+        );
+    }
+
+    #[test]
+    fn test_from_reader() {
+        use std::io::{self, Read};
+
+        /// Hands out one byte per read.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl<'a> Read for Trickle<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match (self.0.split_first(), buf.first_mut()) {
+                    (Some((byte, rest)), Some(dest)) => {
+                        *dest = *byte;
+                        self.0 = rest;
+                        Ok(1)
+                    },
+                    _ => Ok(0)
+                }
+            }
+        }
+
+        let long = "a string which is long enough to need a long form header".repeat(8);
+        let mut stream = to_bytes(&(1_u64, vec!["cat", "dog"])).unwrap();
+        stream.extend(to_bytes(&long).unwrap());
+        stream.extend([0x05, 0x80]);
+
+        for mut reader in [Box::new(&stream[..]) as Box<dyn Read>, Box::new(Trickle(&stream))] {
+            assert_eq!(from_reader::<_, (u64, Vec<String>)>(&mut reader).unwrap(), (1, vec!["cat".into(), "dog".into()]));
+            assert_eq!(from_reader::<_, String>(&mut reader).unwrap(), long);
+            assert_eq!(from_reader::<_, u8>(&mut reader).unwrap(), 5);
+            assert_eq!(from_reader::<_, Option<u8>>(&mut reader).unwrap(), None);
+            assert_eq!(
+                from_reader::<_, u8>(&mut reader), 
+                Err(Error::MalformedData { offset: 0, expected: Expected::Item, found: Found::End })
+            );
+        }
+
+        // the input ends in the header or in the payload
+        assert_eq!(
+            from_reader::<_, String>(&[0xb9, 0x01][..]), 
+            Err(Error::MalformedData { offset: 0, expected: Expected::Item, found: Found::Truncated { declared: 3, available: 2 } })
+        );
+        assert_eq!(
+            from_reader::<_, String>(&[0x83, b'd', b'o'][..]), 
+            Err(Error::MalformedData { offset: 0, expected: Expected::Item, found: Found::Truncated { declared: 4, available: 3 } })
+        );
+        // a hostile header does not allocate what it declares, even without a limit
+        let hostile = [0xbf, 0x0f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(matches!(
+            from_reader_with_config::<_, String>(&hostile[..], Config::new()), 
+            Err(Error::MalformedData { found: Found::Truncated { available: 10, .. }, .. })
+        ));
+        let mut reader = &hostile[..];
+        assert!(matches!(from_reader::<_, String>(&mut reader), Err(Error::InputTooLarge { limit: DEFAULT_MAX_READ, .. })));
+        assert_eq!(reader, [0x01]);
+        let mut large = vec![0xba, 0xa0, 0x00, 0x01];
+        large.resize(4 + DEFAULT_MAX_READ, b'a');
+        assert!(matches!(from_reader::<_, Vec<u8>>(&large[..]), Err(Error::InputTooLarge { limit: DEFAULT_MAX_READ, .. })));
+        large[1..4].copy_from_slice(&[0x9f, 0xff, 0xfc]);
+        assert_eq!(from_reader::<_, serde_bytes::ByteBuf>(&large[..]).unwrap().len(), DEFAULT_MAX_READ - 4);
+        let config = Config::new().with_max_input(1024);
+        let mut reader = &hostile[..];
+        assert!(matches!(from_reader_with_config::<_, String>(&mut reader, config), Err(Error::InputTooLarge { limit: 1024, .. })));
+        // the payload is not read
+        assert_eq!(reader, [0x01]);
+
+        let strict = Config::new().with_strict(true);
+        assert_eq!(from_reader_with_config::<_, String>(&[0xb8, 0x01, b'a'][..], strict), Err(Error::NonCanonical { offset: 0 }));
+        assert_eq!(from_reader_with_config::<_, String>(&[0x81, b'a'][..], strict), Err(Error::NonCanonical { offset: 0 }));
+    }
+}
@@ -1,10 +1,11 @@
-use std::collections::VecDeque;
-use serde::{Serialize, Deserialize};
+use std::{collections::VecDeque, io::{self, Read}};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 
 use crate::{
     ser::Serializer,
-    error::{Result, Error}, 
-    de::Deserializer
+    error::{Result, Error, Expected, Found}, 
+    de::{Deserializer, len_of_len},
+    config::{Config, DEFAULT_MAX_READ}
 };
 
 /// This function serialize a type instance into a byte vector with RLP encoding.
@@ -17,7 +18,7 @@ use crate::{
 /// For example: 
 /// 
 /// ```rust
-/// struct Int(u8)
+/// struct Int(u8);
 /// 
 /// enum Sample {
 ///     Empty,
@@ -40,6 +41,93 @@ where
     Serializer::to_bytes(value)
 }
 
+/// Same as `to_bytes`, but with the given configuration.
+/// See `config::Config` for available options.
+pub fn to_bytes_with_config<T>(value: &T, config: Config) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    Serializer::to_bytes_with_config(value, config)
+}
+
+/// Same as `to_bytes`, but the encoding is written into `buf`, returns the length 
+/// of the encoding. `Error::BufferTooSmall` is returned if it does not fit, the 
+/// content of `buf` is unspecified in that case. Nothing is allocated unless the 
+/// value contains a map, whose entries are sorted by key in a scratch buffer, 
+/// or a sequence of `u8` under `Config::with_u8_seq_as_bytes`.
+/// 
+/// ```rust
+/// # use serlp::rlp::to_slice;
+/// let mut buf = [0; 16];
+/// let len = to_slice(&("cat", "dog"), &mut buf).unwrap();
+/// assert_eq!(buf[..len], [0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']);
+/// ```
+pub fn to_slice<T>(value: &T, buf: &mut [u8]) -> Result<usize>
+where
+    T: Serialize,
+{
+    Serializer::to_slice_with_config(value, buf, Config::default())
+}
+
+/// Same as `to_slice`, but with the given configuration.
+pub fn to_slice_with_config<T>(value: &T, buf: &mut [u8], config: Config) -> Result<usize>
+where
+    T: Serialize,
+{
+    Serializer::to_slice_with_config(value, buf, config)
+}
+
+/// The exact length of `to_bytes(value)`, computed without allocating. 
+/// Useful to check size limits or to pre-size buffers before encoding.
+/// 
+/// ```rust
+/// # use serlp::rlp::{to_bytes, encoded_len};
+/// let value = (vec![0_u64; 100], "dog");
+/// assert_eq!(encoded_len(&value).unwrap(), to_bytes(&value).unwrap().len());
+/// ```
+pub fn encoded_len<T>(value: &T) -> Result<usize>
+where
+    T: Serialize,
+{
+    Serializer::encoded_len_with_config(value, Config::default())
+}
+
+/// Same as `encoded_len`, but with the given configuration.
+pub fn encoded_len_with_config<T>(value: &T, config: Config) -> Result<usize>
+where
+    T: Serialize,
+{
+    Serializer::encoded_len_with_config(value, config)
+}
+
+/// Same as `to_bytes`, but the encoding is written to `writer` without 
+/// building it in memory. The value is serialized twice, the first pass 
+/// computes the length of every list, so that the second pass can write 
+/// the headers before the payloads.
+/// 
+/// ```rust
+/// # use serlp::rlp::{to_bytes, to_writer};
+/// let mut buf = Vec::new();
+/// to_writer(&mut buf, &("cat", "dog")).unwrap();
+/// assert_eq!(buf, to_bytes(&("cat", "dog")).unwrap());
+/// ```
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    Serializer::to_writer_with_config(writer, value, Config::default())
+}
+
+/// Same as `to_writer`, but with the given configuration.
+pub fn to_writer_with_config<W, T>(writer: W, value: &T, config: Config) -> Result<()>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    Serializer::to_writer_with_config(writer, value, config)
+}
+
 /// This function deserialize a byte slice into a type.
 /// It works by construct a tree from the RLP encoded slice.
 /// When serde is deserializing each field, it will call the corresponding
@@ -48,13 +136,149 @@ where
 /// enocde all Rust types, for example, variants. So you may need to implement 
 /// your own deserialize trait for some variant types when nessessary.
 /// This function returns `Error::MalformedData` when the input is not 
-/// valid RLP encoded bytes.
+/// valid RLP encoded bytes, and `Error::TrailingBytes` when there are 
+/// bytes left after the value is decoded.
 pub fn from_bytes<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    deserialize_exact(Deserializer::new(s))
+}
+
+/// Same as `from_bytes`, but only canonical encodings are accepted, 
+/// see `de::Deserializer::new_strict` for what is rejected.
+/// This function returns `Error::NonCanonical` when the input is 
+/// valid but not canonical RLP encoded bytes.
+pub fn from_bytes_strict<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    deserialize_exact(Deserializer::new_strict(s))
+}
+
+/// Same as `from_bytes`, but with the given configuration.
+/// See `config::Config` for available options.
+pub fn from_bytes_with_config<'a, T>(s: &'a [u8], config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    deserialize_exact(Deserializer::with_config(s, config))
+}
+
+/// Deserialize a value from the beginning of a byte slice, returns the value 
+/// together with the unconsumed remainder. This is useful for protocols 
+/// concatenating multiple items.
+/// 
+/// ```rust
+/// # use serlp::rlp::from_bytes_prefix;
+/// let (first, remained): (u8, _) = from_bytes_prefix(&[0x01, 0x83, b'd', b'o', b'g']).unwrap();
+/// let (second, remained): (&str, _) = from_bytes_prefix(remained).unwrap();
+/// assert_eq!((first, second), (1, "dog"));
+/// assert!(remained.is_empty());
+/// ```
+pub fn from_bytes_prefix<'a, T>(s: &'a [u8]) -> Result<(T, &'a [u8])>
 where
     T: Deserialize<'a>,
 {
     let mut deserializer = Deserializer::new(s);
     let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.remaining()))
+}
+
+/// Read exactly one item from `reader` and deserialize it, the reader is left 
+/// right after the item so that the next item can be read from it. 
+/// 
+/// The length of the item is known from its header, the bytes are read into 
+/// a buffer which only grows as they arrive, so a hostile header can not make 
+/// it allocate more than what is actually sent. Items longer than 
+/// `config::DEFAULT_MAX_READ` (10 MiB) are rejected with `Error::InputTooLarge` 
+/// before their payload is read, use `from_reader_with_config` for another limit. 
+/// An input which ends in the middle of the item is reported as `Error::MalformedData`.
+/// 
+/// ```rust
+/// # use serlp::rlp::from_reader;
+/// let mut input: &[u8] = &[0x01, 0x83, b'd', b'o', b'g'];
+/// let first: u8 = from_reader(&mut input).unwrap();
+/// let second: String = from_reader(&mut input).unwrap();
+/// assert_eq!((first, second.as_str()), (1, "dog"));
+/// assert!(input.is_empty());
+/// ```
+pub fn from_reader<R, T>(reader: R) -> Result<T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    from_reader_with_config(reader, Config::new().with_max_input(DEFAULT_MAX_READ))
+}
+
+/// Same as `from_reader`, but with the given configuration. Items are limited 
+/// by `Config::with_max_input` only, which is unlimited unless it is set, so 
+/// set it when reading from an untrusted source.
+pub fn from_reader_with_config<R, T>(reader: R, config: Config) -> Result<T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    let buf = read_item(reader, config)?;
+    from_bytes_with_config(&buf, config)
+}
+
+/// Read the bytes of the next item from `reader`, header included.
+fn read_item<R: io::Read>(mut reader: R, config: Config) -> Result<Vec<u8>> {
+    let truncated = |declared, available| Error::MalformedData {
+        offset: 0,
+        expected: Expected::Item,
+        found: Found::Truncated { declared, available }
+    };
+
+    let mut header = [0_u8; 9];
+    if read_full(&mut reader, &mut header[..1])? == 0 {
+        return Err(Error::MalformedData { offset: 0, expected: Expected::Item, found: Found::End })
+    }
+    let header_len = 1 + len_of_len(header[0]);
+    let read = read_full(&mut reader, &mut header[1..header_len])?;
+    if 1 + read < header_len {
+        return Err(truncated(header_len, 1 + read))
+    }
+
+    let len = Deserializer::with_config(&header[..header_len], config).item_len()?;
+    if len > config.max_input {
+        return Err(Error::InputTooLarge { len, limit: config.max_input })
+    }
+
+    // a single byte below 0x80 is its own header and payload
+    let header_len = header_len.min(len);
+    let mut buf = header[..header_len].to_vec();
+    reader.take((len - header_len) as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(truncated(len, buf.len()))
+    }
+    Ok(buf)
+}
+
+/// Read until `buf` is full or the input ends, returns the number of bytes read.
+fn read_full<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(read)
+}
+
+fn deserialize_exact<'a, T>(mut deserializer: Deserializer<'a>) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    deserializer.check_input_len()?;
+    let t = T::deserialize(&mut deserializer)?;
+    if !deserializer.is_empty() {
+        return Err(Error::TrailingBytes { offset: deserializer.offset() })
+    }
     Ok(t)
 }
 
@@ -80,6 +304,15 @@ pub struct RlpTree<'de> {
     value_count: usize
 }
 
+/// Counts the items while building the tree.
+#[derive(Default)]
+struct Counter {
+    /// the number of byte strings
+    values: usize,
+    /// the number of byte strings and lists
+    nodes: usize
+}
+
 enum TraverseRlp<'de> {
     Found(&'de [u8]),
     Leaf(&'de [u8]),
@@ -88,29 +321,46 @@ enum TraverseRlp<'de> {
 
 impl<'de> RlpTree<'de> {
     pub fn new(buf: &'de [u8]) -> Result<Self> {
+        Self::build(buf, Deserializer::new(buf))
+    }
+
+    /// Build the tree, rejecting non-canonical encodings.
+    /// See `de::Deserializer::new_strict` for details.
+    pub fn new_strict(buf: &'de [u8]) -> Result<Self> {
+        Self::build(buf, Deserializer::new_strict(buf))
+    }
+
+    /// Build the tree with the given configuration. Strictness, the maximum 
+    /// depth and all the limits (`max_input`, `max_string_len`, `max_list_items` 
+    /// and `max_tree_nodes`) apply, the representations are irrelevant.
+    pub fn with_config(buf: &'de [u8], config: Config) -> Result<Self> {
+        Self::build(buf, Deserializer::with_config(buf, config))
+    }
+
+    fn build(buf: &'de [u8], de: Deserializer<'de>) -> Result<Self> {
         if buf.is_empty() {
-            return Err(Error::MalformedData)
+            return Err(Error::MalformedData { offset: 0, expected: Expected::Item, found: Found::End })
         }
+        de.check_input_len()?;
         let mut root = VecDeque::with_capacity(1);
-        let mut value_count = 0;
+        let mut counter = Counter::default();
 
-        let de = Deserializer::new(buf);
-        let (tree, remained) = Self::parse_node(&mut value_count, de)?;
+        let (tree, remained) = Self::parse_node(&mut counter, de)?;
         root.push_back(tree);
         if !remained.is_empty() {
-            Err(Error::MalformedData)
+            Err(Error::TrailingBytes { offset: remained.offset() })
         } else {
             Ok(Self {
                 root: RlpNode {
                     span: buf,
                     value: RlpNodeValue::Compound(root),
                 },
-                value_count
+                value_count: counter.values
             })
         }
     }
 
-    pub fn root(&'de self) -> &RlpNode {
+    pub fn root(&self) -> &RlpNode<'de> {
         if let RlpNodeValue::Compound(root) = &self.root.value {
             root.front().unwrap()
         } else {
@@ -118,7 +368,7 @@ impl<'de> RlpTree<'de> {
         }
     }
 
-    pub fn root_mut(&'de mut self) -> &mut RlpNode {
+    pub fn root_mut(&mut self) -> &mut RlpNode<'de> {
         if let RlpNodeValue::Compound(root) = &mut self.root.value {
             root.front_mut().unwrap()
         } else {
@@ -142,9 +392,14 @@ impl<'de> RlpTree<'de> {
     }
 
     /// parse a single node
-    fn parse_node(counter: &mut usize, de: Deserializer<'de>) -> Result<(RlpNode<'de>, Deserializer<'de>)> {
-        if de.next_is_bytes() {
-            *counter += 1;
+    fn parse_node(counter: &mut Counter, de: Deserializer<'de>) -> Result<(RlpNode<'de>, Deserializer<'de>)> {
+        let limit = de.config().max_tree_nodes;
+        if counter.nodes >= limit {
+            return Err(Error::TooManyNodes { offset: de.offset(), limit })
+        }
+        counter.nodes += 1;
+        if de.next_is_bytes()? {
+            counter.values += 1;
             Self::extract_bytes(de)
         } else {
             Self::extract_seq(counter, de)
@@ -159,12 +414,13 @@ impl<'de> RlpTree<'de> {
         }, new))
     }
 
-    fn extract_seq(counter: &mut usize, de: Deserializer<'de>) -> Result<(RlpNode<'de>, Deserializer<'de>)> {
+    fn extract_seq(counter: &mut Counter, de: Deserializer<'de>) -> Result<(RlpNode<'de>, Deserializer<'de>)> {
         let (span, mut seq, remained) = de.next_seq()?;
 
         // now buf is the inner data
         let mut nodes = VecDeque::new();
         while !seq.is_empty()  {
+            seq.check_items(nodes.len())?;
             let (node, remained) = Self::parse_node(counter, seq)?;
             seq = remained;
             nodes.push_back(node);
@@ -1,27 +1,547 @@
-use serde::{
-    ser::{self, SerializeTuple}, 
-    Serialize
-};
+use std::io;
+use serde::{ser, Serialize};
 use paste::paste;
 
-use crate::error::{Error, Result};
+use crate::{
+    config::{Config, EnumRepr, SignedRepr, UnitRepr},
+    error::{Error, Result},
+    types::Nil
+};
+
+pub struct Serializer<O> {
+    /// where the encoding is written to
+    output: O,
+    config: Config,
+    /// the marker of `None` for the next option, set by `types::nil`, 
+    /// `types::nil_string` and `types::nil_list`
+    nil: Option<Nil>
+}
+
+/// The types which have to be `pub` because they appear in the implementation 
+/// of `serde::Serializer`, but are not reachable from outside of the crate.
+mod private {
+    use std::ops::Range;
+    use crate::error::Result;
+    use super::{Serializer, ScratchWriter};
+
+    /// Where the serializer writes the encoding to.
+    /// 
+    /// The length of a list is only known after its payload is serialized, so 
+    /// the payload of every list is written between `begin` and `end`, 
+    /// an output either buffers the payload or knows the length in advance.
+    pub trait Output {
+        /// Write encoded bytes.
+        fn write(&mut self, bytes: &[u8]) -> Result<()>;
+
+        /// Open a list (or a string if `list` is false) whose payload is written next, 
+        /// returns a handle which is passed to the matching `end`.
+        fn begin(&mut self, list: bool) -> Result<usize>;
+
+        /// Close the frame opened by `begin`, this is where the header is written.
+        fn end(&mut self, frame: usize, list: bool) -> Result<()>;
+
+        /// Called right after the frame of a map is opened, 
+        /// returns how `MapCompound` writes its entries.
+        fn begin_map(&mut self) -> Result<MapMode> {
+            Ok(MapMode::Sorted)
+        }
+
+        /// Write the entries of a map in `MapMode::Sorted`, which are 
+        /// encoded in `scratch` and already sorted by their keys.
+        fn write_entries(&mut self, scratch: &[u8], entries: &[Entry]) -> Result<()> {
+            for entry in entries {
+                self.write(&scratch[entry.span.clone()])?;
+            }
+            Ok(())
+        }
+    }
+
+    /// How the entries of a map are written. They must be sorted by the 
+    /// encoding of their keys, which is only known once they are encoded.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum MapMode {
+        /// The entries are encoded once into a scratch buffer, sorted 
+        /// and then passed to `Output::write_entries`.
+        Sorted,
+        /// The order does not matter, e.g. when only counting bytes, 
+        /// so the entries are written to the output directly.
+        Unsorted,
+        /// The output already wrote the entries, e.g. the ones sorted in 
+        /// the first pass of a two-pass encoding, so they are skipped.
+        Skipped
+    }
+
+    /// A `[key, value]` entry encoded in the scratch buffer of a map.
+    pub struct Entry {
+        /// the whole entry
+        pub(super) span: Range<usize>,
+        /// the encoding of the key, which the entries are sorted by
+        pub(super) key: Range<usize>
+    }
+
+    /// The state of a list being serialized, returned by `serialize_seq` and friends.
+    pub struct Compound<'a, O> {
+        pub(super) ser: &'a mut Serializer<O>,
+        /// the frame of the list, `None` while the sequence may still be 
+        /// a byte string, see `Config::with_u8_seq_as_bytes`
+        pub(super) frame: Option<usize>,
+        /// the elements of a sequence which only has `u8` elements so far
+        pub(super) bytes: Vec<u8>,
+        /// the frame of the enum variant which wraps the list, see `config::EnumRepr`
+        pub(super) variant: Option<usize>
+    }
+
+    /// The state of a map being serialized, see `MapMode`.
+    pub struct MapCompound<'a, O> {
+        pub(super) ser: &'a mut Serializer<O>,
+        /// the frame of the map
+        pub(super) frame: usize,
+        pub(super) mode: MapMode,
+        /// where the entries are encoded in `MapMode::Sorted`, nothing is 
+        /// allocated in the other modes
+        pub(super) scratch: Serializer<ScratchWriter>,
+        pub(super) entries: Vec<Entry>,
+        /// the frame of the entry whose key is serialized and the end of the key
+        pub(super) entry: Option<(usize, usize)>
+    }
+}
+
+use private::{Compound, Entry, MapCompound, MapMode, Output};
+
+/// Write into a caller-provided buffer. A byte is reserved for the header 
+/// when a frame is opened, which is patched when the frame is closed. 
+/// If the payload turns out to be 56 bytes or longer, the payload is shifted 
+/// to make room for the long form header.
+/// 
+/// Once the buffer is too small, the bytes are only counted, 
+/// so that the required size can be reported.
+pub(crate) struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    /// the number of bytes written (or counted) so far
+    len: usize,
+    /// the buffer turned out to be too small
+    overflow: bool
+}
+
+impl<'a> SliceWriter<'a> {
+    /// Whether there is room for `additional` more bytes, 
+    /// the writer only counts from the first time there is not.
+    fn reserve(&mut self, additional: usize) -> bool {
+        self.overflow |= self.buf.len() - self.len.min(self.buf.len()) < additional;
+        !self.overflow
+    }
+}
+
+impl<'a> Output for SliceWriter<'a> {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.reserve(bytes.len()) {
+            self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        }
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn begin(&mut self, _list: bool) -> Result<usize> {
+        self.reserve(1);
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    fn end(&mut self, frame: usize, list: bool) -> Result<()> {
+        let extra = header(self.len - frame - 1, list).1 - 1;
+        if self.reserve(extra) {
+            self.len = patch_header(self.buf, frame, self.len, list);
+        } else {
+            self.len += extra;
+        }
+        Ok(())
+    }
+}
+
+/// Write the header of the frame whose header byte is reserved at `frame` 
+/// and whose payload is `frame + 1..end`. The payload is shifted if the header 
+/// is longer than one byte, `buf` must have room for it. Returns the new end.
+fn patch_header(buf: &mut [u8], frame: usize, end: usize, list: bool) -> usize {
+    let (header, len) = header(end - frame - 1, list);
+    if len > 1 {
+        buf.copy_within(frame + 1..end, frame + len);
+    }
+    buf[frame..frame + len].copy_from_slice(&header[..len]);
+    end + len - 1
+}
+
+/// Where `MapCompound` encodes the entries of a map, in a single pass 
+/// with patched headers like `SliceWriter`, so each entry is encoded once.
+#[derive(Default)]
+pub(crate) struct ScratchWriter {
+    buf: Vec<u8>
+}
+
+impl Output for ScratchWriter {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn begin(&mut self, _list: bool) -> Result<usize> {
+        self.buf.push(0);
+        Ok(self.buf.len() - 1)
+    }
+
+    fn end(&mut self, frame: usize, list: bool) -> Result<()> {
+        let end = self.buf.len();
+        let extra = header(end - frame - 1, list).1 - 1;
+        self.buf.resize(end + extra, 0);
+        patch_header(&mut self.buf, frame, end, list);
+        Ok(())
+    }
+}
+
+/// The first pass of `to_writer` and `to_bytes`, which records the payload length of 
+/// every frame in the order they are opened.
+#[derive(Default)]
+struct Sizer {
+    /// the number of bytes written so far
+    len: usize,
+    /// the start of open frames and the payload length of closed frames
+    lengths: Vec<usize>,
+    /// the sorted entries of every map, so that the second pass does not 
+    /// encode them again
+    maps: Vec<u8>,
+    /// the length of the entries of every map in `maps`
+    map_lens: Vec<usize>
+}
+
+impl Output for Sizer {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn begin(&mut self, _list: bool) -> Result<usize> {
+        self.lengths.push(self.len);
+        Ok(self.lengths.len() - 1)
+    }
+
+    fn end(&mut self, frame: usize, list: bool) -> Result<()> {
+        let payload = self.len - self.lengths[frame];
+        self.lengths[frame] = payload;
+        self.len += header(payload, list).1;
+        Ok(())
+    }
+
+    fn write_entries(&mut self, scratch: &[u8], entries: &[Entry]) -> Result<()> {
+        let start = self.maps.len();
+        for entry in entries {
+            self.maps.extend_from_slice(&scratch[entry.span.clone()]);
+        }
+        self.map_lens.push(self.maps.len() - start);
+        self.len += self.maps.len() - start;
+        Ok(())
+    }
+}
+
+/// The second pass of `to_writer` and `to_bytes`, headers are written 
+/// directly with the lengths recorded by `Sizer`, so no byte is ever moved.
+pub(crate) struct IoWriter<W> {
+    writer: W,
+    /// the number of bytes written so far
+    len: usize,
+    lengths: std::vec::IntoIter<usize>,
+    /// the entries of the maps, sorted by `Sizer`
+    maps: Vec<u8>,
+    map_lens: std::vec::IntoIter<usize>,
+    /// the start of the entries of the next map in `maps`
+    map_start: usize
+}
+
+impl<W: io::Write> Output for IoWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(bytes)?;
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn begin(&mut self, list: bool) -> Result<usize> {
+        let len = self.lengths.next().ok_or_else(changed_between_passes)?;
+        let (header, header_len) = header(len, list);
+        self.write(&header[..header_len])?;
+        Ok(0)
+    }
+
+    fn end(&mut self, _frame: usize, _list: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn begin_map(&mut self) -> Result<MapMode> {
+        let len = self.map_lens.next().ok_or_else(changed_between_passes)?;
+        let start = self.map_start;
+        self.map_start += len;
+        self.writer.write_all(&self.maps[start..start + len])?;
+        self.len += len;
+        Ok(MapMode::Skipped)
+    }
+}
+
+/// Only count the bytes, used by `encoded_len`. The frame handle is where 
+/// the payload starts, so no allocation is needed.
+#[derive(Default)]
+pub(crate) struct SizeCounter {
+    len: usize
+}
+
+impl Output for SizeCounter {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn begin(&mut self, _list: bool) -> Result<usize> {
+        Ok(self.len)
+    }
+
+    fn end(&mut self, frame: usize, list: bool) -> Result<()> {
+        self.len += header(self.len - frame, list).1;
+        Ok(())
+    }
+
+    fn begin_map(&mut self) -> Result<MapMode> {
+        Ok(MapMode::Unsorted)
+    }
+}
+
+/// Only remember whether the first item is a list, used by `types::nil` 
+/// to find out the kind of a value without encoding it.
+#[derive(Default)]
+pub(crate) struct KindProbe {
+    list: Option<bool>
+}
+
+impl Output for KindProbe {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.list.is_none() {
+            self.list = bytes.first().map(|byte| *byte >= 0xc0);
+        }
+        Ok(())
+    }
+
+    fn begin(&mut self, list: bool) -> Result<usize> {
+        self.list.get_or_insert(list);
+        Ok(0)
+    }
 
-pub struct Serializer {
-    /// the parser stack, we simulate recursion with this structure
-    stack: Vec<Vec<u8>>
+    fn end(&mut self, _frame: usize, _list: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn begin_map(&mut self) -> Result<MapMode> {
+        Ok(MapMode::Unsorted)
+    }
 }
 
-impl Serializer {
+fn changed_between_passes() -> Error {
+    ser::Error::custom("the value serialized differently in the sizing pass")
+}
+
+impl Default for Serializer<Vec<u8>> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A serializer can be kept around to reuse its buffer:
+/// 
+/// ```rust
+/// # use serlp::{ser::Serializer, rlp::to_bytes};
+/// let mut serializer = Serializer::new();
+/// for message in ["cat", "dog"] {
+///     serializer.clear();
+///     serializer.serialize_into(&message).unwrap();
+///     assert_eq!(serializer.as_bytes(), to_bytes(&message).unwrap());
+/// }
+/// ```
+impl Serializer<Vec<u8>> {
+    /// A serializer with an empty buffer and the default configuration.
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    /// A serializer with an empty buffer and the given configuration.
+    pub fn with_config(config: Config) -> Self {
+        Self::with_output(Vec::new(), config)
+    }
+
+    /// Append the encoding of `value` to the buffer, which grows at most 
+    /// once. If it fails, the buffer is left as it was before the call.
+    pub fn serialize_into<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let start = self.output.len();
+        let config = self.config;
+        let result = Serializer::size(value, config).and_then(|sizer| {
+            self.output.reserve_exact(sizer.len);
+            Serializer::write_sized(&mut self.output, value, config, sizer)
+        });
+        if result.is_err() {
+            self.output.truncate(start);
+        }
+        result
+    }
+
+    /// The encodings appended so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Empty the buffer but keep its allocation for the next value.
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+
+    /// Take the buffer out of the serializer.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.output
+    }
+
     pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
     where
         T: Serialize,
     {
-        let mut serializer = Serializer {
-            stack: Vec::new()
-        };
-        serializer.stack.push(Vec::new());
+        Self::to_bytes_with_config(value, Config::default())
+    }
+
+    /// Encoded in two passes like `to_writer_with_config`, so that the output 
+    /// is allocated only once and every header is written at its final place.
+    pub fn to_bytes_with_config<T>(value: &T, config: Config) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        let mut serializer = Self::with_config(config);
+        serializer.serialize_into(value)?;
+        Ok(serializer.output)
+    }
+}
+
+impl<'a> Serializer<SliceWriter<'a>> {
+    /// Encode `value` into `buf`, returns the length of the encoding. 
+    /// Maps are sorted as in `MapMode::Sorted`.
+    pub(crate) fn to_slice_with_config<T>(value: &T, buf: &'a mut [u8], config: Config) -> Result<usize>
+    where
+        T: Serialize,
+    {
+        let mut serializer = Serializer::with_output(SliceWriter { buf, len: 0, overflow: false }, config);
+        value.serialize(&mut serializer)?;
+        let SliceWriter { buf, len, overflow } = serializer.output;
+        if overflow {
+            return Err(Error::BufferTooSmall { needed: len, available: buf.len() })
+        }
+        Ok(len)
+    }
+}
+
+impl<W: io::Write> Serializer<IoWriter<W>> {
+    /// Encode `value` in two passes: the first one computes the length of 
+    /// every list, then the second one writes the bytes to `writer` directly.
+    /// `writer` receives many small writes, wrap it in a `io::BufWriter` if 
+    /// that is expensive.
+    pub(crate) fn to_writer_with_config<T>(writer: W, value: &T, config: Config) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let sizer = Serializer::size(value, config)?;
+        Self::write_sized(writer, value, config, sizer)
+    }
+
+    /// The second pass, `sizer` is the result of the first pass.
+    fn write_sized<T>(writer: W, value: &T, config: Config, sizer: Sizer) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let Sizer { len, lengths, maps, map_lens } = sizer;
+        let mut serializer = Serializer::with_output(IoWriter {
+            writer, 
+            len: 0, 
+            lengths: lengths.into_iter(),
+            maps,
+            map_lens: map_lens.into_iter(),
+            map_start: 0
+        }, config);
+        value.serialize(&mut serializer)?;
+        let output = serializer.output;
+        if output.len != len || output.lengths.len() != 0 || output.map_lens.len() != 0 {
+            return Err(changed_between_passes())
+        }
+        Ok(())
+    }
+}
+
+impl Serializer<Sizer> {
+    /// The first pass of a two-pass encoding.
+    fn size<T>(value: &T, config: Config) -> Result<Sizer>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut sizer = Serializer::with_output(Sizer::default(), config);
+        value.serialize(&mut sizer)?;
+        Ok(sizer.output)
+    }
+}
+
+impl Serializer<KindProbe> {
+    /// The marker of `None` which matches the kind of `value` 
+    /// under the default configuration.
+    pub(crate) fn nil_kind<T: ?Sized + Serialize>(value: &T) -> Result<Nil> {
+        let mut probe = Serializer::with_output(KindProbe::default(), Config::default());
+        value.serialize(&mut probe)?;
+        Ok(if probe.output.list == Some(true) { Nil::List } else { Nil::String })
+    }
+}
+
+impl Serializer<SizeCounter> {
+    /// The length of the encoding of `value`. Nothing is allocated on the heap, 
+    /// the entries of maps are counted in any order, see `MapMode::Unsorted`.
+    pub(crate) fn encoded_len_with_config<T>(value: &T, config: Config) -> Result<usize>
+    where
+        T: Serialize,
+    {
+        let mut serializer = Serializer::with_output(SizeCounter::default(), config);
         value.serialize(&mut serializer)?;
-        Ok(serializer.stack.pop().unwrap())
+        Ok(serializer.output.len)
+    }
+}
+
+impl<O> Serializer<O> {
+    fn with_output(output: O, config: Config) -> Self {
+        Self {
+            output,
+            config,
+            nil: None
+        }
+    }
+}
+
+/// The header of a string or a list with a payload of `len` bytes,
+/// the header is the first `.1` bytes of `.0`.
+fn header(len: usize, list: bool) -> ([u8; 9], usize) {
+    let offset = if list { 192 } else { 128 };
+    let mut header = [0; 9];
+    match len as u64 {
+        // (128 + ||x||) \dot x if ||x|| \lt 56
+        // (192 + ||s(x)||) \dot s(x) if s(x) \ne \empty \land ||s(x)|| \lt 56
+        0..=55 => {
+            header[0] = offset + len as u8;
+            (header, 1)
+        },
+        // (183 + ||BE(||x||)||) \dot BE(||x||) \dot x if ||x|| \lt 2^64
+        // (247 + ||BE(||s(x)||)||) \dot BE(||s(x)||) \dot s(x)
+        56..=u64::MAX => {
+            let be_bytes = len.to_be_bytes();
+            let len_be = be_bytes_compact(&be_bytes);
+            header[0] = offset + 55 + len_be.len() as u8;
+            header[1..=len_be.len()].copy_from_slice(len_be);
+            (header, 1 + len_be.len())
+        }
     }
 }
 
@@ -29,7 +549,23 @@ fn be_bytes_compact(src: &[u8]) -> &[u8] {
     for i in 0..src.len() {
         if src[i] != 0 { return &src[i..] }
     }
-    return &[]
+    &[]
+}
+
+/// Remove the leading bytes of a two's complement big endian integer 
+/// which only extend the sign, zero is compacted to an empty slice.
+fn signed_be_bytes_compact(src: &[u8]) -> &[u8] {
+    let sign = if src[0] & 0x80 == 0 { 0x00 } else { 0xff };
+    let mut start = 0;
+    while start < src.len() && src[start] == sign {
+        match src.get(start + 1) {
+            // the next byte still carries the sign bit
+            Some(next) if next & 0x80 == sign & 0x80 => start += 1,
+            None if sign == 0 => start += 1,
+            _ => break
+        }
+    }
+    &src[start..]
 }
 
 macro_rules! impl_seralize_integer {
@@ -42,11 +578,31 @@ macro_rules! impl_seralize_integer {
     }
 }
 
+/// Signed integers are encoded according to `config::SignedRepr`.
+macro_rules! impl_seralize_signed {
+    ($($ity:ident => $uty:ident),+) => {
+        paste! {$(
+            fn [<serialize_ $ity>](self, v: $ity) -> Result<()> {
+                match self.config.signed_repr {
+                    SignedRepr::Unsupported => Err(Error::TypeNotSupported { type_name: stringify!($ity) }),
+                    SignedRepr::ZigZag => {
+                        let zigzag = ((v << 1) ^ (v >> ($ity::BITS - 1))) as $uty;
+                        self.[<serialize_ $uty>](zigzag)
+                    },
+                    SignedRepr::TwosComplement => {
+                        self.serialize_bytes(signed_be_bytes_compact(&v.to_be_bytes()))
+                    }
+                }
+            }
+        )+}
+    }
+}
+
 macro_rules! impl_seralize_not_supported {
     ($($ity:ident),+) => {
         paste! {$(
             fn [<serialize_ $ity>](self, _v: $ity) -> Result<()> {
-                Err(Error::TypeNotSupported)
+                Err(Error::TypeNotSupported { type_name: stringify!($ity) })
             }
         )+}
     }
@@ -54,7 +610,7 @@ macro_rules! impl_seralize_not_supported {
 
 
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl<'a, O: Output> ser::Serializer for &'a mut Serializer<O> {
     // The output type produced by this `Serializer` during successful
     // serialization. Most serializers that produce text or binary output should
     // set `Ok = ()` and serialize into an `io::Write` or buffer contained
@@ -67,26 +623,35 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type Error = Error;
 
     // Associated types for keeping track of additional state while serializing
-    // compound data structures like sequences and maps. In this case no
-    // additional state is required beyond what is already stored in the
-    // Serializer struct.
-    type SerializeSeq = Self;
-    type SerializeTuple = Self;
-    type SerializeTupleStruct = Self;
-    type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
-    type SerializeStruct = Self;
-    type SerializeStructVariant = Self;
-
-    // yellow paper didn't mention how to encode bool and floats
-    impl_seralize_not_supported! {bool, f32, f64, i8, i16, i32, i64}
+    // compound data structures like sequences and maps. The only state we need 
+    // is the frame of the list, which is returned by `Output::begin`.
+    type SerializeSeq = Compound<'a, O>;
+    type SerializeTuple = Compound<'a, O>;
+    type SerializeTupleStruct = Compound<'a, O>;
+    type SerializeTupleVariant = Compound<'a, O>;
+    type SerializeMap = MapCompound<'a, O>;
+    type SerializeStruct = Compound<'a, O>;
+    type SerializeStructVariant = Compound<'a, O>;
+
+    // yellow paper didn't mention how to encode floats
+    impl_seralize_not_supported! {f32, f64}
     
     // according to yellow paper, integers should be encoded as bytes (big endian)
-    impl_seralize_integer! {u8, u16, u32, u64}
+    impl_seralize_integer! {u8, u16, u32, u64, u128}
+
+    // signed integers are not mentioned in the yellow paper either, 
+    // they are rejected unless a representation is chosen
+    impl_seralize_signed! {i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128}
+
+    /// Booleans are encoded as integers like go-ethereum does, 
+    /// i.e. false is 0x80 and true is 0x01.
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.serialize_u8(v as u8)
+    }
 
     /// Serialize a char as a single-character string. 
     fn serialize_char(self, v: char) -> Result<()> {
-        self.serialize_str(&v.to_string())
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
     }
 
     /// strings are bytes. THE YELLOW PAPER IS ALWAYS RIGHT!!!
@@ -97,133 +662,164 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     /// YELLOW PAPER told us how to encode a byte array.
     /// LONG LIVE THE YELLOW PAPER!
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        let last = self.stack.last_mut().unwrap();
-        match v.len() as u64 {
+        match v {
             // x if ||x|| = 1 \land x[0] \lt 128
-            1 if v[0] < 128 => last.extend(v),
-            // (128 + ||x||) \dot x if ||x|| \lt 56
-            0..=55 =>  {
-                last.push(128 + v.len() as u8);
-                last.extend(v);
-            },
-            // (183 + ||BE(||x||)||) \dot BE(||x||) \dot x if ||x|| \lt 2^64
-            56..=u64::MAX => {
-                let be_bytes = v.len().to_be_bytes();
-                let len_be = be_bytes_compact(&be_bytes);
-                last.push(183 + len_be.len() as u8);
-                last.extend(len_be);
-                last.extend(v);
+            [single] if *single < 128 => self.output.write(v),
+            _ => {
+                let (header, len) = header(v.len(), false);
+                self.output.write(&header[..len])?;
+                self.output.write(v)
             }
         }
-        
-        Ok(())
     }
 
     /// nothing
     /// So what is the difference between (), (()), None, "" and []
-    /// none just means nothing, it not even an empty list
+    /// none just means nothing, it not even an empty list.
+    /// 
+    /// Use `types::nil_list` or `Config::with_none_repr` if `None` should be 
+    /// an empty list.
     fn serialize_none(self) -> Result<()> {
-        let last = self.stack.last_mut().unwrap();
-        last.push(0x80);
-        Ok(())
+        let marker = match self.nil.take() {
+            Some(Nil::List) => UnitRepr::EmptyList,
+            Some(Nil::String) => UnitRepr::EmptyString,
+            None => self.config.none_repr
+        };
+        self.serialize_empty(marker)
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        // the marker only applies to the outermost option
+        self.nil = None;
         value.serialize(self)
     }
 
     /// unit is an empty tuple.
     /// In our design principle, an empty tuple is an empty list.
-    /// So it should be encoded.
+    /// So it should be encoded. See `Config::with_unit_repr`.
     fn serialize_unit(self) -> Result<()> {
-        let unit = self.serialize_tuple(0)?;
-        unit.end()
+        self.serialize_empty(self.config.unit_repr)
     }
 
     /// unit struct in NOT even an empty tuple.
-    /// It's just a mark. So we serialize it as none.
+    /// It's just a mark. So we serialize it as none by default,
+    /// see `Config::with_unit_struct_repr`.
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
-        self.serialize_none()
+        self.serialize_empty(self.config.unit_struct_repr)
     }
 
-    /// Note we are **LOSING** information here.
+    /// Note we are **LOSING** information here by default.
     /// We dropped the variant index of this enum so you cannot
     /// deserialize it.
     /// We have to choose this method because there is no enums in Golang 
     /// but eth is written in go. Treating enums as a transparent layer 
     /// can make our furture implementation compatiable with ETH.
+    /// 
+    /// Use `config::EnumRepr` to keep the variant index.
     fn serialize_unit_variant(
         self,
-        _name: &'static str,
-        _variant_index: u32,
+        name: &'static str,
+        variant_index: u32,
         _variant: &'static str,
     ) -> Result<()> {
-        self.serialize_none()
+        match self.config.enum_repr {
+            EnumRepr::Transparent => self.serialize_empty(self.config.unit_variant_repr),
+            EnumRepr::Indexed => {
+                let frame = self.variant_begin(name, variant_index)?;
+                self.variant_return(frame)
+            },
+            EnumRepr::TypeByte => self.serialize_bytes(&[type_byte(name, variant_index)?])
+        }
     }
 
     /// This is TRANSPARENT!
     fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        self.nil = Nil::from_name(name);
         value.serialize(self)
     }
 
-    /// TRANSPARENT! But we do not support it.
-    /// What a pity.
+    /// TRANSPARENT by default! See `config::EnumRepr`.
     fn serialize_newtype_variant<T>(
         self,
-        _name: &'static str,
-        _variant_index: u32,
+        name: &'static str,
+        variant_index: u32,
         _variant: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(self)
+        if self.config.enum_repr == EnumRepr::Transparent {
+            return value.serialize(self)
+        }
+        let frame = self.variant_begin(name, variant_index)?;
+        value.serialize(&mut *self)?;
+        self.variant_return(frame)
     }
 
     /// serialize a sequence, the sequence will be parsed recursively
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.stack.push(Vec::new());
-        Ok(self)
+        Compound::begin_seq(self)
     }
     
+    /// Fixed size arrays are tuples too, so tuples of `u8` are byte 
+    /// strings if `Config::with_u8_seq_as_bytes` is set.
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        self.stack.push(Vec::new());
-        Ok(self)
+        Compound::begin_seq(self)
     }
 
-    /// There is only a tuple
+    /// Always a list, whatever the fields are
     fn serialize_tuple_struct(
         self,
         _name: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        self.serialize_tuple(len)
+        Compound::begin(self, None)
     }
 
+    /// The fields are serialized as a list, the list is wrapped 
+    /// according to `config::EnumRepr`.
     fn serialize_tuple_variant(
         self,
-        _name: &'static str,
-        _variant_index: u32,
+        name: &'static str,
+        variant_index: u32,
         _variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.serialize_tuple(len)
+        let variant = match self.config.enum_repr {
+            EnumRepr::Transparent => None,
+            _ => Some(self.variant_begin(name, variant_index)?)
+        };
+        Compound::begin(self, variant)
     }
 
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        self.stack.push(Vec::new());
-        Ok(self)
+    /// A map is a list of `[key, value]` pairs sorted by the encoding of 
+    /// the keys, so that the encoding does not depend on the iteration order.
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        let frame = self.output.begin(true)?;
+        let mode = self.output.begin_map()?;
+        let entries = match mode {
+            MapMode::Sorted => Vec::with_capacity(len.unwrap_or(0)),
+            _ => Vec::new()
+        };
+        Ok(MapCompound {
+            scratch: Serializer::with_output(ScratchWriter::default(), self.config),
+            ser: self,
+            frame,
+            mode,
+            entries,
+            entry: None
+        })
     }
 
     /// We parse struct as we are parsing a sequence
@@ -232,24 +828,77 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct> {
-        self.stack.push(Vec::new());
-        Ok(self)
+        Compound::begin(self, None)
     }
 
     fn serialize_struct_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
-        _variant: &'static str,
+        variant_index: u32,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.serialize_struct(name, len)
+        self.serialize_tuple_variant(name, variant_index, variant, len)
+    }
+}
+
+impl<'a, O: Output> Compound<'a, O> {
+    fn begin(ser: &'a mut Serializer<O>, variant: Option<usize>) -> Result<Self> {
+        let frame = Some(ser.output.begin(true)?);
+        Ok(Self { ser, frame, bytes: Vec::new(), variant })
+    }
+
+    /// Begin a sequence or a tuple, which is a byte string if all the 
+    /// elements are `u8` and `Config::with_u8_seq_as_bytes` is set.
+    fn begin_seq(ser: &'a mut Serializer<O>) -> Result<Self> {
+        if !ser.config.u8_seq_as_bytes {
+            return Self::begin(ser, None)
+        }
+        Ok(Self { ser, frame: None, bytes: Vec::new(), variant: None })
+    }
+
+    fn element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.frame.is_none() {
+            let mut byte = None;
+            if value.serialize(&mut U8Probe(&mut byte)).is_ok() {
+                if let Some(byte) = byte {
+                    self.bytes.push(byte);
+                    return Ok(())
+                }
+            }
+            // it turns out to be a list, the bytes so far are its first elements
+            self.frame = Some(self.ser.output.begin(true)?);
+            for byte in std::mem::take(&mut self.bytes) {
+                ser::Serializer::serialize_u8(&mut *self.ser, byte)?;
+            }
+        }
+        value.serialize(&mut *self.ser)
+    }
+
+    /// Close the list, and the variant wrapping it if any. 
+    /// An empty sequence has no element to tell its type, so it is still a list.
+    fn end(self) -> Result<()> {
+        match self.frame {
+            Some(frame) => self.ser.output.end(frame, true)?,
+            None if self.bytes.is_empty() => {
+                let frame = self.ser.output.begin(true)?;
+                self.ser.output.end(frame, true)?
+            },
+            None => ser::Serializer::serialize_bytes(&mut *self.ser, &self.bytes)?
+        }
+        match self.variant {
+            Some(frame) => self.ser.variant_return(frame),
+            None => Ok(())
+        }
     }
 }
 
 /// This impl is SerializeSeq so these methods are called after `serialize_seq`
 /// is called on the Serializer.
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeSeq for Compound<'a, O> {
     // Must match the `Ok` type of the serializer.
     type Ok = ();
     // Must match the `Error` type of the serializer.
@@ -260,18 +909,17 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     // Close the sequence.
     fn end(self) -> Result<()> {
-        self.frame_return();
-        Ok(())
+        Compound::end(self)
     }
 }
 
 // Same thing but for tuples.
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeTuple for Compound<'a, O> {
     type Ok = ();
     type Error = Error;
 
@@ -279,17 +927,16 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        self.frame_return();
-        Ok(())
+        Compound::end(self)
     }
 }
 
 // Same thing but for tuple structs.
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeTupleStruct for Compound<'a, O> {
     type Ok = ();
     type Error = Error;
 
@@ -297,12 +944,11 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        self.frame_return();
-        Ok(())
+        Compound::end(self)
     }
 }
 
@@ -315,7 +961,7 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
 //
 // So the `end` method in this impl is responsible for closing both the `]` and
 // the `}`.
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeTupleVariant for Compound<'a, O> {
     type Ok = ();
     type Error = Error;
 
@@ -323,23 +969,121 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.frame_return();
+        Compound::end(self)
+    }
+}
+
+/// Find out whether a value is a `u8`, see `Compound::element`. 
+/// Everything else is rejected before anything is serialized.
+struct U8Probe<'a>(&'a mut Option<u8>);
+
+fn not_u8() -> Error {
+    Error::TypeNotSupported { type_name: "u8" }
+}
+
+impl<'a, 'b> ser::Serializer for &'a mut U8Probe<'b> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeTuple = ser::Impossible<(), Error>;
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    impl_seralize_not_supported! {bool, i8, i16, i32, i64, i128, u16, u32, u64, u128, f32, f64, char}
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        *self.0 = Some(v);
         Ok(())
     }
+
+    fn serialize_str(self, _v: &str) -> Result<()> { Err(not_u8()) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> { Err(not_u8()) }
+    fn serialize_none(self) -> Result<()> { Err(not_u8()) }
+    fn serialize_unit(self) -> Result<()> { Err(not_u8()) }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<()> {
+        Err(not_u8())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Err(not_u8())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, _variant: &'static str) -> Result<()> {
+        Err(not_u8())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, _value: &T) -> Result<()> {
+        Err(not_u8())
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T
+    ) -> Result<()> {
+        Err(not_u8())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(not_u8())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(not_u8())
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> {
+        Err(not_u8())
+    }
+
+    fn serialize_tuple_variant(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(not_u8())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(not_u8())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(not_u8())
+    }
+
+    fn serialize_struct_variant(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(not_u8())
+    }
 }
 
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeMap for MapCompound<'a, O> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T>(&mut self, _key: &T) -> Result<()>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        self.entry = match self.mode {
+            MapMode::Sorted => {
+                let frame = self.scratch.output.begin(true)?;
+                key.serialize(&mut self.scratch)?;
+                Some((frame, self.scratch.output.buf.len()))
+            },
+            MapMode::Unsorted => {
+                let frame = self.ser.output.begin(true)?;
+                key.serialize(&mut *self.ser)?;
+                Some((frame, 0))
+            },
+            MapMode::Skipped => Some((0, 0))
+        };
         Ok(())
     }
     
@@ -347,18 +1091,42 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        let (frame, key_end) = self.entry.take()
+            .ok_or_else(|| <Error as ser::Error>::custom("serialize_value called before serialize_key"))?;
+        match self.mode {
+            MapMode::Sorted => {
+                value.serialize(&mut self.scratch)?;
+                let end = self.scratch.output.buf.len();
+                self.scratch.output.end(frame, true)?;
+                // the bytes after the reserved header byte are shifted if the header is longer
+                let shift = self.scratch.output.buf.len() - end;
+                self.entries.push(Entry { 
+                    span: frame..end + shift, 
+                    key: frame + 1 + shift..key_end + shift 
+                });
+                Ok(())
+            },
+            MapMode::Unsorted => {
+                value.serialize(&mut *self.ser)?;
+                self.ser.output.end(frame, true)
+            },
+            MapMode::Skipped => Ok(())
+        }
     }
 
-    fn end(self) -> Result<()> {
-        self.frame_return();
-        Ok(())
+    fn end(mut self) -> Result<()> {
+        if self.mode == MapMode::Sorted {
+            let scratch = &self.scratch.output.buf;
+            self.entries.sort_unstable_by(|a, b| scratch[a.key.clone()].cmp(&scratch[b.key.clone()]));
+            self.ser.output.write_entries(scratch, &self.entries)?;
+        }
+        self.ser.output.end(self.frame, true)
     }
 }
 
 // Structs are like maps in which the keys are constrained to be compile-time
 // constant strings.
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeStruct for Compound<'a, O> {
     type Ok = ();
     type Error = Error;
 
@@ -366,45 +1134,58 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.frame_return();
-        Ok(())
+        Compound::end(self)
     }
 }
 
-impl Serializer {
-    fn frame_return(&mut self) {
-        // s(x)
-        let frame = self.stack.pop().unwrap();
-        // ||s(x)||
-        let len = frame.len();
-
-        let last = self.stack.last_mut().unwrap();
+impl<O: Output> Serializer<O> {
+    /// Write a value without data, see `config::UnitRepr`.
+    fn serialize_empty(&mut self, repr: UnitRepr) -> Result<()> {
+        match repr {
+            UnitRepr::EmptyString => self.output.write(&[0x80]),
+            UnitRepr::EmptyList => self.output.write(&[0xc0])
+        }
+    }
 
-        match len as u64 {
-            // (192 + ||s(x)||) \dot s(x) if s(x) \ne \empty \land ||s(x)|| \lt 56
-            0..=55 => {
-                last.push(192 + len as u8);
-                last.extend(frame);
+    /// Open the frame of an enum variant and put the variant index in it,
+    /// the frame is closed by `variant_return`.
+    fn variant_begin(&mut self, name: &'static str, variant_index: u32) -> Result<usize> {
+        match self.config.enum_repr {
+            // index || RLP(payload), the payload is never empty so the 
+            // string is always longer than a single byte
+            EnumRepr::TypeByte => {
+                let frame = self.output.begin(false)?;
+                self.output.write(&[type_byte(name, variant_index)?])?;
+                Ok(frame)
             },
-            56..=u64::MAX => {
-                let be_bytes = len.to_be_bytes();
-                let len_be = be_bytes_compact(&be_bytes);
-                last.push(247 + len_be.len() as u8);
-                last.extend(len_be);
-                last.extend(frame);
+            // [index, payload]
+            _ => {
+                let frame = self.output.begin(true)?;
+                ser::Serializer::serialize_u32(&mut *self, variant_index)?;
+                Ok(frame)
             }
         }
-        
+    }
+
+    fn variant_return(&mut self, frame: usize) -> Result<()> {
+        let list = self.config.enum_repr != EnumRepr::TypeByte;
+        self.output.end(frame, list)
     }
 }
 
+/// The variant index is used as the type byte in `EnumRepr::TypeByte`,
+/// enums with more than 256 variants are not supported.
+fn type_byte(name: &'static str, variant_index: u32) -> Result<u8> {
+    u8::try_from(variant_index).or(Err(Error::TypeNotSupported { type_name: name }))
+}
+
 // Similar to `SerializeTupleVariant`, here the `end` method is responsible for
 // closing both of the curly braces opened by `serialize_struct_variant`.
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl<'a, O: Output> ser::SerializeStructVariant for Compound<'a, O> {
     type Ok = ();
     type Error = Error;
     
@@ -412,12 +1193,10 @@ impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.frame_return();
-        Ok(())
+        Compound::end(self)
     }
 }
-
@@ -1,14 +1,221 @@
-/// This module provides two (de)serializers for frequently used types in blockchain.
-/// - `biguint` for `num_bigint::BigUint`
-/// - `byte_array` for `[u8; N]`
+//! This module provides (de)serializers for frequently used types in blockchain.
+//! - `biguint` for `num_bigint::BigUint`
+//! - `byte_array` for `[u8; N]`
+//! - `nil`, `nil_string` and `nil_list` for `Option<T>`
+//! 
+//! Put `#[serde(with = "biguint")]` or `#[serde(with = "byte_array")]` before your 
+//! struct **field** to use them.
+
+use std::marker::PhantomData;
+use serde::{de::{Visitor, Deserialize}, Deserializer};
+
+use crate::error::Error;
+
+/// The marker used to encode `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Nil {
+    /// an empty string, 0x80
+    String,
+    /// an empty list, 0xc0
+    List
+}
+
+/// The (de)serializer sees these names as newtype struct names, 
+/// which is how we pass the marker through serde.
+const NIL_STRING: &str = "$serlp::NilString";
+const NIL_LIST: &str = "$serlp::NilList";
+
+impl Nil {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            NIL_STRING => Some(Nil::String),
+            NIL_LIST => Some(Nil::List),
+            _ => None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Nil::String => NIL_STRING,
+            Nil::List => NIL_LIST
+        }
+    }
+}
+
+struct NilVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for NilVisitor<T>
+where
+    T: Deserialize<'de>
+{
+    type Value = Option<T>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an optional value")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        Option::deserialize(deserializer)
+    }
+}
+
+fn deserialize_nil<'de, D, T>(nil: Nil, deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>
+{
+    deserializer.deserialize_newtype_struct(nil.name(), NilVisitor(PhantomData))
+}
+
+/// Encode `None` as an empty string (0x80), which is also the default 
+/// behaviour of a bare `Option<T>`. When deserializing, only an empty 
+/// string is decoded as `None`. Corresponds to `rlp:"nilString"` in go-ethereum.
+pub mod nil_string {
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use super::Nil;
+
+    pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize
+    {
+        serializer.serialize_newtype_struct(Nil::String.name(), value)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>
+    {
+        super::deserialize_nil(Nil::String, deserializer)
+    }
+}
+
+/// Encode `None` as an empty list (0xc0). When deserializing, only an empty 
+/// list is decoded as `None`. Corresponds to `rlp:"nilList"` in go-ethereum.
+pub mod nil_list {
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use super::Nil;
+
+    pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize
+    {
+        serializer.serialize_newtype_struct(Nil::List.name(), value)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>
+    {
+        super::deserialize_nil(Nil::List, deserializer)
+    }
+}
+
+/// Find out whether `T` is decoded from a string or a list without any input, 
+/// by looking at which method of the deserializer `T` calls first. 
+/// The probe fails right there, the kind is read from it afterwards.
+struct NilProbe(Nil);
+
+impl NilProbe {
+    fn nil_kind<'de, T: Deserialize<'de>>() -> Nil {
+        let mut probe = NilProbe(Nil::String);
+        let _ = T::deserialize(&mut probe);
+        probe.0
+    }
+
+    fn found<V>(&mut self, nil: Nil) -> Result<V, Error> {
+        self.0 = nil;
+        Err(Error::TypeNotSupported { type_name: "nil" })
+    }
+}
+
+impl<'de> Deserializer<'de> for &mut NilProbe {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        self.found(Nil::String)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        self.found(Nil::List)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        match Nil::from_name(name) {
+            Some(nil) => self.found(nil),
+            None => visitor.visit_newtype_struct(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        self.found(Nil::List)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Error> {
+        self.found(Nil::List)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self, _name: &'static str, _len: usize, _visitor: V
+    ) -> Result<V::Value, Error> {
+        self.found(Nil::List)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        self.found(Nil::List)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self, _name: &'static str, _fields: &'static [&'static str], _visitor: V
+    ) -> Result<V::Value, Error> {
+        self.found(Nil::List)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit_struct enum identifier ignored_any
+    }
+}
+
+/// Choose the marker of `None` from the kind of `T`, just like `rlp:"nil"` 
+/// in go-ethereum: an empty list if `T` is encoded as a list, otherwise an 
+/// empty string. When deserializing, only that marker is decoded as `None`.
 /// 
-/// Put `#[serde(with = "biguint")]` or `#[serde(with = "byte_array")]` before your 
-/// struct **field** to use them.
+/// The kind is found from `T::default()` when serializing and from the way 
+/// `T` is deserialized when deserializing, both under the default 
+/// representations of `config::Config`. Nothing is encoded or decoded for it.
+pub mod nil {
+    use serde::{Serialize, Serializer, Deserialize, Deserializer, ser::Error};
+    use crate::ser;
+
+    pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize + Default
+    {
+        let nil = ser::Serializer::nil_kind(&T::default()).map_err(S::Error::custom)?;
+        serializer.serialize_newtype_struct(nil.name(), value)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>
+    {
+        super::deserialize_nil(super::NilProbe::nil_kind::<T>(), deserializer)
+    }
+}
 
 
 pub mod biguint {
     use num_bigint::BigUint;
-    use serde::{Serializer, Deserializer};
+    use serde::{Serializer, Deserializer, de::{Error, Unexpected}};
     use serde_bytes;
     
     pub fn serialize<S>(bn: &BigUint, serializer: S) -> Result<S::Ok, S::Error>
@@ -23,12 +230,19 @@ pub mod biguint {
         serde_bytes::serialize(&bytes, serializer)
     }
 
-    /// This takes the result of [`serde_bytes::deserialize`] from `[u8]` to `[u8; N]`.
+    /// This takes the result of [`serde_bytes::deserialize`] from `[u8]` to `BigUint`.
+    /// Just like other integers, leading zero bytes are rejected.
     pub fn deserialize<'de, D>(deserializer: D) -> Result<BigUint, D::Error>
     where
         D: Deserializer<'de>,
     {
         let slice: &[u8] = serde_bytes::deserialize(deserializer)?;
+        if slice.first() == Some(&0) {
+            return Err(D::Error::invalid_value(
+                Unexpected::Bytes(slice), 
+                &"a big endian integer without leading zeros"
+            ))
+        }
         Ok(BigUint::from_bytes_be(slice))
     }
 }